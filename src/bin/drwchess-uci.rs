@@ -0,0 +1,22 @@
+// engine for chess GUIs and tournament tools, speaking UCI or, when the
+// first command is "xboard", the XBoard protocol over the standard input
+// and output
+use drwchess::engine::{uci, xboard};
+
+use std::io::{self, BufRead, Cursor, Read};
+
+fn main() {
+    let mut input = io::stdin().lock();
+    let mut first = String::new();
+    if input.read_line(&mut first).is_err() {
+        return;
+    }
+    let xboard = first.trim() == "xboard";
+    // the first command is handed over with the rest of the input
+    let input = io::BufReader::new(Cursor::new(first).chain(input));
+    if xboard {
+        xboard::run(input, io::stdout());
+    } else {
+        uci::run(input, io::stdout());
+    }
+}
@@ -66,30 +66,3 @@ pub mod move_data {
         data
     }
 }
-
-pub mod canvas_display {
-    use sdl2::rect::Rect;
-    use sdl2::render::{Texture, WindowCanvas};
-
-    pub fn canvas_fill(canvas: &mut WindowCanvas, rect: Rect) {
-        match canvas.fill_rect(rect) {
-            Ok(_) => {}
-            Err(msg) => {
-                println!("Error: {}", msg)
-            }
-        }
-    }
-    pub fn canvas_copy(
-        canvas: &mut WindowCanvas,
-        texture: &Texture,
-        rect1: Option<Rect>,
-        rect2: Option<Rect>,
-    ) {
-        match canvas.copy(texture, rect1, rect2) {
-            Ok(_) => {}
-            Err(msg) => {
-                println!("Error: {}", msg)
-            }
-        }
-    }
-}
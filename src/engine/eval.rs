@@ -0,0 +1,569 @@
+use crate::models::bitboard::{self, Attacks, Bitboard};
+use crate::models::board::Board;
+use crate::models::piece::{PColor, PieceType};
+use crate::models::position::Position;
+
+use std::ops::{Add, AddAssign, Mul, Sub};
+
+// centipawn value of each piece type, indexed by `PieceType as usize`; the
+// king cannot be traded so it is not counted
+pub const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 0];
+
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+];
+
+// A term is scored twice, for the middlegame and for the endgame, the two
+// being blended by the material left on the board
+#[derive(Eq, PartialEq, Debug, Default, Copy, Clone)]
+struct Score {
+    mg: i32,
+    eg: i32,
+}
+
+const fn s(mg: i32, eg: i32) -> Score {
+    Score { mg, eg }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, other: Score) -> Score {
+        s(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, other: Score) -> Score {
+        s(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+
+    fn mul(self, factor: i32) -> Score {
+        s(self.mg * factor, self.eg * factor)
+    }
+}
+
+// material of each piece type in both phases
+const MATERIAL: [Score; 6] = [
+    s(82, 94),
+    s(337, 281),
+    s(365, 297),
+    s(477, 512),
+    s(1025, 936),
+    s(0, 0),
+];
+
+// weight of the pieces in the game phase, 24 being the full middlegame
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+
+// Piece-square tables seen from white, a8 first; black reads them with
+// the ranks flipped. Only pawns and kings play differently in the endgame.
+#[rustfmt::skip]
+const PAWN_MG: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    10,  10,  20,  30,  30,  20,  10,  10,
+     5,   5,  10,  25,  25,  10,   5,   5,
+     0,   0,   0,  20,  20,   0,   0,   0,
+     5,  -5, -10,   0,   0, -10,  -5,   5,
+     5,  10,  10, -20, -20,  10,  10,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const PAWN_EG: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    40,  40,  40,  40,  40,  40,  40,  40,
+    25,  25,  25,  25,  25,  25,  25,  25,
+    15,  15,  15,  15,  15,  15,  15,  15,
+     8,   8,   8,   8,   8,   8,   8,   8,
+     4,   4,   4,   4,   4,   4,   4,   4,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT: [i32; 64] = [
+   -50, -40, -30, -30, -30, -30, -40, -50,
+   -40, -20,   0,   0,   0,   0, -20, -40,
+   -30,   0,  10,  15,  15,  10,   0, -30,
+   -30,   5,  15,  20,  20,  15,   5, -30,
+   -30,   0,  15,  20,  20,  15,   0, -30,
+   -30,   5,  10,  15,  15,  10,   5, -30,
+   -40, -20,   0,   5,   5,   0, -20, -40,
+   -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP: [i32; 64] = [
+   -20, -10, -10, -10, -10, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,  10,  10,   5,   0, -10,
+   -10,   5,   5,  10,  10,   5,   5, -10,
+   -10,   0,  10,  10,  10,  10,   0, -10,
+   -10,  10,  10,  10,  10,  10,  10, -10,
+   -10,   5,   0,   0,   0,   0,   5, -10,
+   -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,  10,  10,  10,  10,  10,  10,   5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+     0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN: [i32; 64] = [
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+    -5,   0,   5,   5,   5,   5,   0,  -5,
+     0,   0,   5,   5,   5,   5,   0,  -5,
+   -10,   5,   5,   5,   5,   5,   0, -10,
+   -10,   0,   5,   0,   0,   0,   0, -10,
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const KING_MG: [i32; 64] = [
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -20, -30, -30, -40, -40, -30, -30, -20,
+   -10, -20, -20, -20, -20, -20, -20, -10,
+    20,  20,   0,   0,   0,   0,  20,  20,
+    20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+#[rustfmt::skip]
+const KING_EG: [i32; 64] = [
+   -50, -40, -30, -20, -20, -30, -40, -50,
+   -30, -20, -10,   0,   0, -10, -20, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -30,   0,   0,   0,   0, -30, -30,
+   -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+// middlegame and endgame tables of each piece type
+const TABLES: [(&[i32; 64], &[i32; 64]); 6] = [
+    (&PAWN_MG, &PAWN_EG),
+    (&KNIGHT, &KNIGHT),
+    (&BISHOP, &BISHOP),
+    (&ROOK, &ROOK),
+    (&QUEEN, &QUEEN),
+    (&KING_MG, &KING_EG),
+];
+
+// pawn structure
+const DOUBLED_PAWN: Score = s(-10, -20);
+const ISOLATED_PAWN: Score = s(-15, -10);
+// by rank counted from the side of the pawn's owner, the first rank first
+const PASSED_PAWN: [Score; 8] = [
+    s(0, 0),
+    s(5, 10),
+    s(10, 20),
+    s(15, 35),
+    s(25, 60),
+    s(40, 100),
+    s(60, 150),
+    s(0, 0),
+];
+
+// king safety, which only matters while there are pieces to attack
+const SHIELD_PAWN: [i32; 2] = [12, 6];
+const OPEN_FILE_NEAR_KING: i32 = -20;
+// weight of an attack on a square next to the king, by attacking type
+const KING_ATTACK_WEIGHTS: [i32; 6] = [0, 2, 2, 3, 5, 0];
+const KING_ATTACK_SCALE: i32 = 6;
+
+// bonus per square a piece can go to, around the usual count of squares
+const MOBILITY: [(Score, i32); 6] = [
+    (s(0, 0), 0),
+    (s(4, 4), 4),
+    (s(5, 5), 7),
+    (s(2, 4), 7),
+    (s(1, 2), 14),
+    (s(0, 0), 0),
+];
+
+// squares of the a-file, shifted to get the other files
+const FILE_A: Bitboard = 0x0101_0101_0101_0101;
+
+// Static score of the position in centipawns, from the point of view of
+// the player to move: material, piece placement, pawn structure, king
+// safety and mobility, blended between middlegame and endgame values.
+pub fn evaluate(position: &Position) -> i32 {
+    let board = &position.board;
+    let mut score = Score::default();
+    for (color, sign) in [(PColor::White, 1), (PColor::Black, -1)] {
+        let side = material(board, color)
+            + placement(board, color)
+            + pawn_structure(board, color)
+            + king_safety(board, color)
+            + mobility(board, color);
+        score += side * sign;
+    }
+    let score = taper(score, phase(board));
+    match position.current_player {
+        PColor::White => score,
+        PColor::Black => -score,
+    }
+}
+
+// -------------------------------------------
+// ------------ PRIVATE FUNCTIONS ------------
+// -------------------------------------------
+
+// from 0 with only kings and pawns left, to MAX_PHASE with every piece
+fn phase(board: &Board) -> i32 {
+    let mut phase = 0;
+    for r#type in PIECE_TYPES {
+        let count = (board.pieces(r#type, PColor::White) | board.pieces(r#type, PColor::Black))
+            .count_ones() as i32;
+        phase += PHASE_WEIGHTS[r#type as usize] * count;
+    }
+    phase.min(MAX_PHASE)
+}
+
+fn taper(score: Score, phase: i32) -> i32 {
+    (score.mg * phase + score.eg * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+// square of the tables standing for the square of a piece of the color
+fn relative_square(color: PColor, square: usize) -> usize {
+    match color {
+        PColor::White => square,
+        PColor::Black => square ^ 56,
+    }
+}
+
+// rank of the square counted from the side of the color, 0 to 7
+fn relative_rank(color: PColor, square: usize) -> usize {
+    7 - relative_square(color, square) / 8
+}
+
+fn file_mask(file: usize) -> Bitboard {
+    FILE_A << file
+}
+
+// files next to the file
+fn adjacent_files(file: usize) -> Bitboard {
+    let mut mask = 0;
+    if file > 0 {
+        mask |= file_mask(file - 1);
+    }
+    if file < 7 {
+        mask |= file_mask(file + 1);
+    }
+    mask
+}
+
+// squares of the ranks in front of the square, from the color's side
+fn ranks_ahead(color: PColor, square: usize) -> Bitboard {
+    let row = square / 8;
+    match color {
+        // rows above hold the lower squares
+        PColor::White => (1u64 << (row * 8)) - 1,
+        PColor::Black if row == 7 => 0,
+        PColor::Black => !0u64 << ((row + 1) * 8),
+    }
+}
+
+fn material(board: &Board, color: PColor) -> Score {
+    let mut score = Score::default();
+    for r#type in PIECE_TYPES {
+        let count = board.pieces(r#type, color).count_ones() as i32;
+        score += MATERIAL[r#type as usize] * count;
+    }
+    score
+}
+
+fn placement(board: &Board, color: PColor) -> Score {
+    let mut score = Score::default();
+    for r#type in PIECE_TYPES {
+        let (mg, eg) = TABLES[r#type as usize];
+        for square in bitboard::squares(board.pieces(r#type, color)) {
+            let square = relative_square(color, square);
+            score += s(mg[square], eg[square]);
+        }
+    }
+    score
+}
+
+fn pawn_structure(board: &Board, color: PColor) -> Score {
+    let pawns = board.pieces(PieceType::Pawn, color);
+    let enemy_pawns = board.pieces(PieceType::Pawn, color.opposite());
+    let mut score = Score::default();
+
+    for file in 0..8 {
+        let count = (pawns & file_mask(file)).count_ones() as i32;
+        if count > 1 {
+            score += DOUBLED_PAWN * (count - 1);
+        }
+    }
+    for square in bitboard::squares(pawns) {
+        let file = square % 8;
+        if pawns & adjacent_files(file) == 0 {
+            score += ISOLATED_PAWN;
+        }
+        // no enemy pawn can stop or take it on its way
+        let front_span = (file_mask(file) | adjacent_files(file)) & ranks_ahead(color, square);
+        if enemy_pawns & front_span == 0 {
+            score += PASSED_PAWN[relative_rank(color, square)];
+        }
+    }
+    score
+}
+
+fn king_safety(board: &Board, color: PColor) -> Score {
+    let king = match board.king_square(color) {
+        None => return Score::default(),
+        Some(square) => square,
+    };
+    let pawns = board.pieces(PieceType::Pawn, color);
+    let attacks = Attacks::get();
+    let mut safety = 0;
+
+    // pawns right in front of the king, or one square further
+    let king_file = king % 8;
+    for file in king_file.saturating_sub(1)..=(king_file + 1).min(7) {
+        let ahead = pawns & file_mask(file) & ranks_ahead(color, king);
+        let closest = bitboard::squares(ahead)
+            .map(|square| relative_rank(color, square) - relative_rank(color, king))
+            .min();
+        match closest {
+            Some(distance) if distance <= 2 => safety += SHIELD_PAWN[distance - 1],
+            Some(_) => {}
+            None => safety += OPEN_FILE_NEAR_KING,
+        }
+    }
+
+    // enemy pieces aiming at the squares around the king
+    let zone = attacks.king(king) | bitboard::bit(king);
+    let enemy = color.opposite();
+    let occupied = board.occupied();
+    let mut pressure = 0;
+    for r#type in [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ] {
+        for square in bitboard::squares(board.pieces(r#type, enemy)) {
+            let hits = (piece_attacks(r#type, square, occupied) & zone).count_ones() as i32;
+            pressure += KING_ATTACK_WEIGHTS[r#type as usize] * hits;
+        }
+    }
+    safety -= pressure * KING_ATTACK_SCALE;
+
+    s(safety, 0)
+}
+
+fn mobility(board: &Board, color: PColor) -> Score {
+    let attacks = Attacks::get();
+    let occupied = board.occupied();
+    // squares guarded by enemy pawns are not worth counting
+    let mut unsafe_squares = 0;
+    for square in bitboard::squares(board.pieces(PieceType::Pawn, color.opposite())) {
+        unsafe_squares |= attacks.pawn(color.opposite(), square);
+    }
+    let available = !board.color(color) & !unsafe_squares;
+
+    let mut score = Score::default();
+    for r#type in [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ] {
+        let (weight, usual) = MOBILITY[r#type as usize];
+        for square in bitboard::squares(board.pieces(r#type, color)) {
+            let count = (piece_attacks(r#type, square, occupied) & available).count_ones() as i32;
+            score += weight * (count - usual);
+        }
+    }
+    score
+}
+
+fn piece_attacks(r#type: PieceType, square: usize, occupied: Bitboard) -> Bitboard {
+    let attacks = Attacks::get();
+    match r#type {
+        PieceType::Knight => attacks.knight(square),
+        PieceType::Bishop => attacks.bishop(square, occupied),
+        PieceType::Rook => attacks.rook(square, occupied),
+        PieceType::Queen => attacks.queen(square, occupied),
+        PieceType::King => attacks.king(square),
+        PieceType::Pawn => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(notation: &str) -> Position {
+        Position::from_fen(notation).unwrap()
+    }
+
+    fn board(notation: &str) -> Board {
+        position(notation).board
+    }
+
+    // the same position with the colors swapped and the board upside down
+    fn mirror(notation: &str) -> String {
+        let fields: Vec<&str> = notation.split(' ').collect();
+        let swap_case = |text: &str| -> String {
+            text.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect()
+        };
+        let placement: Vec<&str> = fields[0].split('/').rev().collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let en_passant = match fields[3] {
+            "-" => String::from("-"),
+            square => square
+                .chars()
+                .map(|c| match c {
+                    '3' => '6',
+                    '6' => '3',
+                    c => c,
+                })
+                .collect(),
+        };
+        format!(
+            "{} {} {} {} {} {}",
+            swap_case(&placement.join("/")),
+            side,
+            swap_case(fields[2]),
+            en_passant,
+            fields[4],
+            fields[5]
+        )
+    }
+
+    #[test]
+    fn both_colors_are_scored_alike() {
+        assert_eq!(evaluate(&Position::new()), 0);
+        for notation in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "4k3/8/8/3pP3/8/8/8/4K3 b - - 0 1",
+        ] {
+            assert_eq!(
+                evaluate(&position(notation)),
+                evaluate(&position(&mirror(notation))),
+                "{}",
+                notation
+            );
+        }
+    }
+
+    #[test]
+    fn material_counts_most() {
+        // white is a knight up, whoever is to move
+        let white = evaluate(&position("4k3/8/8/8/8/8/8/3NK3 w - - 0 1"));
+        let black = evaluate(&position("4k3/8/8/8/8/8/8/3NK3 b - - 0 1"));
+        assert!(white > 200);
+        assert_eq!(black, -white);
+    }
+
+    #[test]
+    fn phase_follows_the_pieces_left() {
+        assert_eq!(phase(&Position::new().board), MAX_PHASE);
+        assert_eq!(
+            phase(&board("4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1")),
+            0
+        );
+        assert_eq!(taper(s(100, 0), MAX_PHASE), 100);
+        assert_eq!(taper(s(100, 0), 0), 0);
+        assert_eq!(taper(s(100, 0), 12), 50);
+
+        // a central king is a liability in the middlegame only
+        let centre = |notation| placement(&board(notation), PColor::White);
+        let early =
+            centre("4k3/8/8/8/4K3/8/8/8 w - - 0 1") - centre("4k3/8/8/8/8/8/8/6K1 w - - 0 1");
+        assert!(early.mg < 0 && early.eg > 0);
+    }
+
+    #[test]
+    fn pawn_weaknesses_and_passers() {
+        let structure = |notation: &str| pawn_structure(&board(notation), PColor::White);
+
+        // doubled and isolated pawns on the c-file, against a healthy pair
+        let weak = structure("4k3/8/8/8/2P5/2P5/8/4K3 w - - 0 1");
+        assert_eq!(
+            weak,
+            DOUBLED_PAWN + ISOLATED_PAWN * 2 + PASSED_PAWN[2] + PASSED_PAWN[3]
+        );
+        let healthy = structure("4k3/p7/8/8/8/8/1PP5/4K3 w - - 0 1");
+        assert_eq!(healthy, PASSED_PAWN[1]);
+
+        // the e-pawn is held back by the d-pawn, the h-pawn runs free
+        let passers = structure("4k3/3p4/8/4P2P/8/8/8/4K3 w - - 0 1");
+        assert_eq!(passers, ISOLATED_PAWN * 2 + PASSED_PAWN[4]);
+        // black reads the ranks the other way
+        let black = pawn_structure(&board("4k3/8/8/8/8/7p/8/4K3 w - - 0 1"), PColor::Black);
+        assert_eq!(black, ISOLATED_PAWN + PASSED_PAWN[5]);
+    }
+
+    #[test]
+    fn sheltered_king_is_safer() {
+        let safety = |notation: &str| king_safety(&board(notation), PColor::White).mg;
+        let castled = safety("r5k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let exposed = safety("r5k1/5ppp/8/8/8/8/PPP5/6K1 w - - 0 1");
+        assert_eq!(castled, SHIELD_PAWN[0] * 3);
+        assert!(exposed < castled);
+
+        // a queen aiming at the king adds to the danger
+        let attacked = safety("6k1/5ppp/8/8/8/8/5PPP/q5K1 w - - 0 1");
+        assert!(attacked < castled);
+    }
+
+    #[test]
+    fn active_pieces_score_higher() {
+        let white = |notation: &str| mobility(&board(notation), PColor::White);
+        let open = white("4k3/8/8/8/3B4/8/8/4K3 w - - 0 1");
+        let locked = white("4k3/8/8/2P1P3/3B4/2P1P3/8/4K3 w - - 0 1");
+        assert!(open.mg > locked.mg);
+
+        // squares guarded by enemy pawns do not count
+        let guarded = white("4k3/3p4/p5p1/8/3N4/8/8/4K3 w - - 0 1");
+        let free = white("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1");
+        assert_eq!(free - guarded, MOBILITY[PieceType::Knight as usize].0 * 4);
+    }
+}
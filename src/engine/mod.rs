@@ -0,0 +1,7 @@
+// computer player: position evaluation, move search and the protocols
+// letting other programs use it
+pub mod eval;
+pub mod search;
+pub mod tt;
+pub mod uci;
+pub mod xboard;
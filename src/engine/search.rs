@@ -0,0 +1,545 @@
+use super::eval::{self, PIECE_VALUES};
+use super::tt::{Bound, TranspositionTable, DEFAULT_SIZE_MB};
+
+use crate::models::position::Position;
+use crate::models::r#move::{Move, MoveGenerator};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// bounds of the scores, mates being scored MATE minus their distance in plies
+pub const INFINITY: i32 = 32_000;
+pub const MATE: i32 = 31_000;
+// scores beyond this bound announce a mate
+pub const MATE_BOUND: i32 = MATE - 1_000;
+
+// deepest line the search may follow, quiescence included
+const MAX_PLY: usize = 96;
+// the clock and node count are only checked this often
+const CHECK_INTERVAL: u64 = 1024;
+
+// when to stop searching, no limit meaning until stopped from outside
+#[derive(Eq, PartialEq, Debug, Default, Copy, Clone)]
+pub struct Limits {
+    pub depth: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub nodes: Option<u64>,
+}
+
+// result of a completed iteration of the search
+#[derive(Debug, Clone)]
+pub struct SearchInfo {
+    pub depth: u32,
+    // centipawns from the point of view of the player to move
+    pub score: i32,
+    pub nodes: u64,
+    pub time: Duration,
+    // principal variation, the best move first
+    pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+    // number of moves to the announced mate, negative when getting mated
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score >= MATE_BOUND {
+            Some((MATE - self.score + 1) / 2)
+        } else if self.score <= -MATE_BOUND {
+            Some(-(MATE + self.score) / 2)
+        } else {
+            None
+        }
+    }
+}
+
+// Iterative deepening negamax with alpha-beta pruning, followed by a
+// quiescence search of captures so lines are never cut in the middle of
+// an exchange. Results are kept in a transposition table. Moves are tried
+// best first: the best move stored for the position, then captures by value
+// of the victim and attacker, promotions and the quiet moves that caused a
+// cutoff at the same ply.
+pub struct Engine {
+    generator: MoveGenerator,
+    limits: Limits,
+    // set to abort the search, possibly from another thread
+    stop: Arc<AtomicBool>,
+    started: Instant,
+    nodes: u64,
+    tt: TranspositionTable,
+    // best line found from each ply of the current path
+    pv: Vec<Vec<Move>>,
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    // best root move of the previous iteration, searched first
+    root_best: Option<Move>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine {
+            generator: MoveGenerator::new(),
+            limits: Limits::default(),
+            stop: Arc::new(AtomicBool::new(false)),
+            started: Instant::now(),
+            nodes: 0,
+            tt: TranspositionTable::new(DEFAULT_SIZE_MB),
+            pv: vec![Vec::new(); MAX_PLY + 1],
+            killers: [[None; 2]; MAX_PLY],
+            root_best: None,
+        }
+    }
+
+    // replaces the transposition table by an empty one of the given size
+    pub fn set_hash_size(&mut self, megabytes: usize) {
+        self.tt = TranspositionTable::new(megabytes);
+    }
+
+    // forgets every position searched, e.g. before a new game
+    pub fn clear_hash(&mut self) {
+        self.tt.clear();
+    }
+
+    // share of the transposition table filled by the last search, in
+    // thousandths
+    pub fn hashfull(&self) -> usize {
+        self.tt.hashfull()
+    }
+
+    // flag ending the running search as soon as it is set, even before it
+    // starts; it is lowered again when the search returns
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    // best move of the position, None when the player to move has none
+    pub fn search(&mut self, position: &mut Position, limits: Limits) -> Option<Move> {
+        self.search_with(position, limits, |_| {})
+    }
+
+    // same as `search`, reporting each completed iteration
+    pub fn search_with(
+        &mut self,
+        position: &mut Position,
+        limits: Limits,
+        mut report: impl FnMut(&SearchInfo),
+    ) -> Option<Move> {
+        self.limits = limits;
+        self.started = Instant::now();
+        self.nodes = 0;
+        self.killers = [[None; 2]; MAX_PLY];
+        self.root_best = None;
+        self.tt.new_search();
+
+        let legal = self.legal_moves(position);
+        let mut best = match legal.first() {
+            None => {
+                self.stop.store(false, Ordering::Relaxed);
+                return None;
+            }
+            Some(mv) => *mv,
+        };
+        let max_depth = limits.depth.unwrap_or(MAX_PLY as u32 / 2).max(1);
+        for depth in 1..=max_depth {
+            let score = self.negamax(position, depth, 0, -INFINITY, INFINITY);
+            // an unfinished iteration cannot be trusted
+            if self.stopped() {
+                break;
+            }
+            best = self.pv[0].first().copied().unwrap_or(best);
+            self.root_best = Some(best);
+            let info = SearchInfo {
+                depth,
+                score,
+                nodes: self.nodes,
+                time: self.started.elapsed(),
+                pv: self.pv[0].clone(),
+            };
+            report(&info);
+
+            // nothing better than a forced mate can be found deeper, and
+            // the next iteration would not end in the time left
+            let mate_found = info
+                .mate_in()
+                .is_some_and(|moves| moves.unsigned_abs() * 2 <= depth);
+            let time_short = limits
+                .movetime
+                .is_some_and(|movetime| info.time * 2 > movetime);
+            if mate_found || time_short || (legal.len() == 1 && limits.depth.is_none()) {
+                break;
+            }
+        }
+        self.stop.store(false, Ordering::Relaxed);
+        Some(best)
+    }
+
+    // -------------------------------------------
+    // ------------ PRIVATE FUNCTIONS ------------
+    // -------------------------------------------
+
+    fn negamax(
+        &mut self,
+        position: &mut Position,
+        depth: u32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.pv[ply].clear();
+        if self.should_stop() {
+            return 0;
+        }
+        if ply > 0 && is_draw(position) {
+            return 0;
+        }
+        let in_check = self
+            .generator
+            .is_in_check(&position.board, position.current_player);
+        // checks are searched one ply deeper so mates are not missed
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth == 0 || ply >= MAX_PLY / 2 {
+            return self.quiescence(position, ply, alpha, beta);
+        }
+        self.nodes += 1;
+
+        // a search of the position at least as deep may settle it already
+        let original_alpha = alpha;
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.probe(position.hash) {
+            tt_move = entry.best_move;
+            let score = score_from_tt(entry.score, ply);
+            let settled = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if ply > 0 && entry.depth >= depth && settled {
+                return score;
+            }
+        }
+
+        let mut moves = self.legal_moves(position);
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        let first = if ply == 0 {
+            self.root_best.or(tt_move)
+        } else {
+            tt_move
+        };
+        self.order_moves(&mut moves, ply, first);
+
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        for mv in moves {
+            position.make_move(mv);
+            let score = -self.negamax(position, depth - 1, ply + 1, -beta, -alpha);
+            position.unmake_move();
+            if self.stopped() {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                best_move = Some(mv);
+            }
+            if score > alpha {
+                alpha = score;
+                let mut line = vec![mv];
+                line.extend_from_slice(&self.pv[ply + 1]);
+                self.pv[ply] = line;
+            }
+            if alpha >= beta {
+                if !mv.is_capture() && mv.promotion().is_none() {
+                    self.store_killer(mv, ply);
+                }
+                break;
+            }
+        }
+
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best <= original_alpha {
+            Bound::Upper
+        } else {
+            Bound::Exact
+        };
+        // no move is stored when none rose above alpha
+        let best_move = if bound == Bound::Upper {
+            None
+        } else {
+            best_move
+        };
+        self.tt.store(
+            position.hash,
+            depth,
+            score_to_tt(best, ply),
+            bound,
+            best_move,
+        );
+        best
+    }
+
+    // searches captures and promotions only, until the position is quiet
+    fn quiescence(
+        &mut self,
+        position: &mut Position,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.pv[ply].clear();
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        // the player may decline every capture
+        let stand_pat = eval::evaluate(position);
+        if stand_pat >= beta || ply >= MAX_PLY {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves: Vec<Move> = self
+            .legal_moves(position)
+            .into_iter()
+            .filter(|mv| mv.is_capture() || mv.promotion().is_some())
+            .collect();
+        self.order_moves(&mut moves, ply, None);
+
+        let mut best = stand_pat;
+        for mv in moves {
+            position.make_move(mv);
+            let score = -self.quiescence(position, ply + 1, -beta, -alpha);
+            position.unmake_move();
+            if self.stopped() {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+
+    fn legal_moves(&self, position: &mut Position) -> Vec<Move> {
+        self.generator
+            .generate_moves(position)
+            .into_values()
+            .flatten()
+            .collect()
+    }
+
+    // best looking moves first, squares breaking ties so the search does
+    // not depend on the order of the generator
+    fn order_moves(&self, moves: &mut [Move], ply: usize, first: Option<Move>) {
+        moves.sort_by_cached_key(|mv| {
+            (
+                std::cmp::Reverse(self.move_score(*mv, ply, first)),
+                mv.start(),
+                mv.end(),
+            )
+        });
+    }
+
+    fn move_score(&self, mv: Move, ply: usize, first: Option<Move>) -> i32 {
+        if Some(mv) == first {
+            return 1_000_000;
+        }
+        let value = |r#type| PIECE_VALUES[r#type as usize];
+        let mut score = 0;
+        if let Some(victim) = mv.captured() {
+            // most valuable victim, least valuable attacker
+            score += 100_000 + 10 * value(victim.r#type) - value(mv.piece().r#type);
+        }
+        if let Some(r#type) = mv.promotion() {
+            score += 90_000 + value(r#type);
+        }
+        if score == 0 {
+            if self.killers[ply][0] == Some(mv) {
+                score = 80_000;
+            } else if self.killers[ply][1] == Some(mv) {
+                score = 79_000;
+            }
+        }
+        score
+    }
+
+    fn store_killer(&mut self, mv: Move, ply: usize) {
+        if self.killers[ply][0] != Some(mv) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(mv);
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    // checks the limits from time to time, raising the stop flag when one
+    // of them is reached
+    fn should_stop(&mut self) -> bool {
+        if self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            let out_of_time = self
+                .limits
+                .movetime
+                .is_some_and(|movetime| self.started.elapsed() >= movetime);
+            let out_of_nodes = self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes);
+            if out_of_time || out_of_nodes {
+                self.stop.store(true, Ordering::Relaxed);
+            }
+        }
+        self.stopped()
+    }
+}
+
+// Mate scores count the plies from the root, while the table is shared by
+// every path to a position: they are stored counted from the position.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+// draws the search does not need to look past; a single repetition is
+// enough as the players could repeat again
+fn is_draw(position: &Position) -> bool {
+    position.halfmove_clock >= 100
+        || position.repetitions() >= 2
+        || position.board.has_insufficient_material()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best_move(notation: &str, depth: u32) -> (String, SearchInfo) {
+        let mut position = Position::from_fen(notation).unwrap();
+        let mut engine = Engine::new();
+        let mut last = None;
+        let limits = Limits {
+            depth: Some(depth),
+            ..Limits::default()
+        };
+        let mv = engine
+            .search_with(&mut position, limits, |info| last = Some(info.clone()))
+            .unwrap();
+        // the position is given back as it was
+        assert_eq!(position.to_fen(), notation);
+        (mv.to_string(), last.unwrap())
+    }
+
+    #[test]
+    fn finds_mates() {
+        let (mv, info) = best_move("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 2);
+        assert_eq!(mv, "a1a8");
+        assert_eq!(info.mate_in(), Some(1));
+
+        // the king closes the net before the rook mates
+        let (_, info) = best_move("k7/8/2K5/8/8/8/8/1R6 w - - 0 1", 4);
+        assert_eq!(info.mate_in(), Some(2));
+
+        // the side getting mated sees it coming
+        let (_, info) = best_move("7k/8/6K1/8/8/8/8/R7 b - - 0 1", 3);
+        assert_eq!(info.mate_in(), Some(-1));
+    }
+
+    #[test]
+    fn wins_material_and_sees_exchanges() {
+        // the queen is left hanging
+        let (mv, _) = best_move("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 2);
+        assert_eq!(mv, "d2d5");
+
+        // the pawn is defended, taking it loses the queen
+        let (mv, info) = best_move("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1", 1);
+        assert_ne!(mv, "d1d5");
+        assert!(info.score > 600);
+    }
+
+    #[test]
+    fn transpositions_are_not_searched_again() {
+        let mut position = Position::new();
+        let limits = Limits {
+            depth: Some(3),
+            ..Limits::default()
+        };
+        let mut engine = Engine::new();
+        engine.search(&mut position, limits);
+        let first = engine.nodes;
+
+        // the table is kept from one search to the next
+        engine.search(&mut position, limits);
+        assert!(engine.nodes < first / 2);
+
+        engine.clear_hash();
+        engine.search(&mut position, limits);
+        assert_eq!(engine.nodes, first);
+
+        // mates are still found through the table, and at the same distance
+        engine.set_hash_size(1);
+        let mut position = Position::from_fen("k7/8/2K5/8/8/8/8/1R6 w - - 0 1").unwrap();
+        let limits = Limits {
+            depth: Some(5),
+            ..Limits::default()
+        };
+        for _ in 0..2 {
+            let mut mate = None;
+            engine.search_with(&mut position, limits, |info| mate = info.mate_in());
+            assert_eq!(mate, Some(2));
+        }
+    }
+
+    #[test]
+    fn no_move_without_legal_moves() {
+        let mut stalemate = Position::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(
+            Engine::new().search(&mut stalemate, Limits::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn stops_within_the_limits() {
+        let mut position = Position::new();
+        let mut engine = Engine::new();
+        let limits = Limits {
+            nodes: Some(2_000),
+            ..Limits::default()
+        };
+        assert!(engine.search(&mut position, limits).is_some());
+        assert!(engine.nodes < 2_000 + CHECK_INTERVAL);
+
+        assert!(!engine.stop_flag().load(Ordering::Relaxed));
+
+        // a stop raised before the search ends it at once, with a move
+        engine.stop_flag().store(true, Ordering::Relaxed);
+        assert!(engine.search(&mut position, Limits::default()).is_some());
+        assert!(!engine.stop_flag().load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::models::r#move::Move;
+
+use std::mem;
+
+// size of the table used by the engine unless told otherwise
+pub const DEFAULT_SIZE_MB: usize = 16;
+
+// what the stored score tells about the real score of the position
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum Bound {
+    Exact,
+    // the search failed high: the real score is at least this much
+    Lower,
+    // the search failed low: the real score is at most this much
+    Upper,
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct Entry {
+    // full Zobrist key, as several positions share each slot
+    pub key: u64,
+    pub depth: u32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+    // search the entry was written in
+    generation: u8,
+}
+
+// Results of the search indexed by the Zobrist key of the position, so a
+// position reached again through another move order is not searched twice.
+// Each key maps to a single slot: an entry is replaced by a deeper search
+// of any position, or by any search once it is left from an older one.
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    generation: u8,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::new(DEFAULT_SIZE_MB)
+    }
+}
+
+impl TranspositionTable {
+    // the largest table fitting in the given size, one entry at least
+    pub fn new(megabytes: usize) -> TranspositionTable {
+        let count = megabytes * 1024 * 1024 / mem::size_of::<Option<Entry>>();
+        // a power of two lets the key be masked into an index
+        let count = match count {
+            0 => 1,
+            count => 1 << count.ilog2(),
+        };
+        TranspositionTable {
+            entries: vec![None; count],
+            generation: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+        self.generation = 0;
+    }
+
+    // to be called before each search, so entries of the previous ones
+    // give way to the new results
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        self.entries[self.index(key)].filter(|entry| entry.key == key)
+    }
+
+    pub fn store(
+        &mut self,
+        key: u64,
+        depth: u32,
+        score: i32,
+        bound: Bound,
+        best_move: Option<Move>,
+    ) {
+        let index = self.index(key);
+        let generation = self.generation;
+        let mut best_move = best_move;
+        if let Some(old) = self.entries[index] {
+            let same = old.key == key;
+            if !same && old.generation == generation && old.depth > depth {
+                return;
+            }
+            // a search failing low finds no move, the old one is kept
+            if same && best_move.is_none() {
+                best_move = old.best_move;
+            }
+        }
+        self.entries[index] = Some(Entry {
+            key,
+            depth,
+            score,
+            bound,
+            best_move,
+            generation,
+        });
+    }
+
+    // share of the table in use by the current search, in thousandths,
+    // estimated from the first entries
+    pub fn hashfull(&self) -> usize {
+        let sample = self.entries.len().min(1000);
+        let used = self.entries[..sample]
+            .iter()
+            .flatten()
+            .filter(|entry| entry.generation == self.generation)
+            .count();
+        used * 1000 / sample
+    }
+
+    // -------------------------------------------
+    // ------------ PRIVATE FUNCTIONS ------------
+    // -------------------------------------------
+
+    fn index(&self, key: u64) -> usize {
+        (key as usize) & (self.entries.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_follows_the_megabytes() {
+        let table = TranspositionTable::new(1);
+        assert!(table.capacity().is_power_of_two());
+        assert!(table.capacity() * mem::size_of::<Option<Entry>>() <= 1024 * 1024);
+        assert!(table.capacity() * 2 * mem::size_of::<Option<Entry>>() > 1024 * 1024);
+        assert_eq!(TranspositionTable::new(0).capacity(), 1);
+    }
+
+    #[test]
+    fn hashfull_counts_the_current_search() {
+        let mut table = TranspositionTable::new(0);
+        assert_eq!(table.hashfull(), 0);
+        table.store(1, 1, 0, Bound::Exact, None);
+        assert_eq!(table.hashfull(), 1000);
+        table.new_search();
+        assert_eq!(table.hashfull(), 0);
+    }
+
+    #[test]
+    fn stores_and_replaces_entries() {
+        let mut table = TranspositionTable::new(1);
+        let key = 0x1234_5678_9abc_def0;
+        // another key of the same slot
+        let other = key + table.capacity() as u64;
+
+        assert_eq!(table.probe(key), None);
+        table.store(key, 5, 40, Bound::Exact, None);
+        let entry = table.probe(key).unwrap();
+        assert_eq!(
+            (entry.depth, entry.score, entry.bound),
+            (5, 40, Bound::Exact)
+        );
+        assert_eq!(table.probe(other), None);
+
+        // a shallower search of another position keeps the deeper entry
+        table.store(other, 3, 10, Bound::Lower, None);
+        assert_eq!(table.probe(other), None);
+        assert!(table.probe(key).is_some());
+
+        // until the entry is left from a previous search
+        table.new_search();
+        table.store(other, 3, 10, Bound::Lower, None);
+        assert_eq!(table.probe(key), None);
+        assert_eq!(table.probe(other).unwrap().bound, Bound::Lower);
+
+        // the same position is always updated
+        table.store(other, 1, -20, Bound::Upper, None);
+        assert_eq!(table.probe(other).unwrap().score, -20);
+
+        table.clear();
+        assert_eq!(table.probe(other), None);
+        assert_eq!(table.hashfull(), 0);
+    }
+}
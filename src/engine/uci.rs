@@ -0,0 +1,371 @@
+use super::search::{Engine, Limits, SearchInfo};
+use super::tt::DEFAULT_SIZE_MB;
+
+use crate::models::piece::PColor;
+use crate::models::position::Position;
+
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub const NAME: &str = concat!("drwchess ", env!("CARGO_PKG_VERSION"));
+pub const AUTHOR: &str = "dweatherstone";
+
+const MAX_HASH_MB: usize = 1024;
+// time kept aside for the GUI to receive the move
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+// moves the remaining time is shared between when the GUI does not tell
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+// Speaks the Universal Chess Interface: commands are read line by line and
+// the answers written to the output, while searches run on their own
+// thread so "stop" and "isready" are answered at once. Returns on "quit" or
+// at the end of the input, once the running search is over.
+pub fn run<R: BufRead, W: Write + Send + 'static>(input: R, output: W) {
+    let mut uci = Uci::new(output);
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if !uci.handle(&line) {
+            uci.stop();
+            return;
+        }
+    }
+    uci.finish();
+}
+
+// share of the remaining time given to a move
+pub fn allot(time: Duration, increment: Duration, moves_to_go: Option<u32>) -> Duration {
+    let moves = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+    let share = time / moves + increment * 3 / 4;
+    // never more than half of what is left
+    share
+        .min(time.saturating_sub(MOVE_OVERHEAD) / 2)
+        .max(Duration::from_millis(1))
+}
+
+// "info" line of a completed iteration
+pub fn info_line(info: &SearchInfo) -> String {
+    let score = match info.mate_in() {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", info.score),
+    };
+    let millis = info.time.as_millis() as u64;
+    let nps = info.nodes * 1000 / millis.max(1);
+    let mut line = format!(
+        "info depth {} score {} nodes {} nps {} time {}",
+        info.depth, score, info.nodes, nps, millis
+    );
+    if !info.pv.is_empty() {
+        let pv: Vec<String> = info.pv.iter().map(|mv| mv.to_string()).collect();
+        line.push_str(&format!(" pv {}", pv.join(" ")));
+    }
+    line
+}
+
+// -------------------------------------------
+// ------------ PRIVATE FUNCTIONS ------------
+// -------------------------------------------
+
+struct Uci<W> {
+    output: Arc<Mutex<W>>,
+    engine: Arc<Mutex<Engine>>,
+    // stop flag of the engine
+    stop: Arc<AtomicBool>,
+    // lets an infinite search give its move, once the GUI asks for it
+    released: Arc<AtomicBool>,
+    position: Position,
+    search: Option<JoinHandle<()>>,
+    infinite: bool,
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+    fn new(output: W) -> Uci<W> {
+        let engine = Engine::new();
+        Uci {
+            output: Arc::new(Mutex::new(output)),
+            stop: engine.stop_flag(),
+            engine: Arc::new(Mutex::new(engine)),
+            released: Arc::new(AtomicBool::new(false)),
+            position: Position::new(),
+            search: None,
+            infinite: false,
+        }
+    }
+
+    // returns false once the GUI asks to quit
+    fn handle(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            None => return true,
+            Some((command, args)) => (*command, args),
+        };
+        match command {
+            "uci" => {
+                self.send(&format!("id name {}", NAME));
+                self.send(&format!("id author {}", AUTHOR));
+                self.send(&format!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_SIZE_MB, MAX_HASH_MB
+                ));
+                // the search runs on a single thread
+                self.send("option name Threads type spin default 1 min 1 max 1");
+                self.send("uciok");
+            }
+            "isready" => self.send("readyok"),
+            "ucinewgame" => {
+                self.stop();
+                self.engine.lock().unwrap().clear_hash();
+                self.position = Position::new();
+            }
+            "position" => {
+                self.stop();
+                self.set_position(args);
+            }
+            "go" => self.go(args),
+            "stop" => self.stop(),
+            "setoption" => self.set_option(args),
+            "quit" => return false,
+            _ => self.send(&format!("info string unknown command '{}'", command)),
+        }
+        true
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.output, line);
+    }
+
+    // "startpos" or "fen <notation>", followed by "moves" and the moves
+    // played since, in UCI notation
+    fn set_position(&mut self, args: &[&str]) {
+        let moves_index = args
+            .iter()
+            .position(|word| *word == "moves")
+            .unwrap_or(args.len());
+        let mut position = match args.first() {
+            Some(&"startpos") => Position::new(),
+            Some(&"fen") => match Position::from_fen(&args[1..moves_index].join(" ")) {
+                Ok(position) => position,
+                Err(msg) => {
+                    self.send(&format!("info string invalid FEN: {}", msg));
+                    return;
+                }
+            },
+            _ => {
+                self.send("info string expected 'startpos' or 'fen'");
+                return;
+            }
+        };
+        for text in args.iter().skip(moves_index + 1) {
+            match position.parse_uci(text) {
+                Ok(mv) => position.make_move(mv),
+                Err(msg) => {
+                    self.send(&format!("info string {}", msg));
+                    break;
+                }
+            }
+        }
+        self.position = position;
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        self.stop();
+
+        let mut limits = Limits::default();
+        let (mut white, mut black) = (None, None);
+        let (mut white_increment, mut black_increment) = (Duration::ZERO, Duration::ZERO);
+        let mut moves_to_go = None;
+        let mut infinite = false;
+        let mut words = args.iter();
+        while let Some(word) = words.next() {
+            let mut number = || words.next().and_then(|value| value.parse::<u64>().ok());
+            match *word {
+                "depth" => limits.depth = number().map(|depth| depth as u32),
+                "nodes" => limits.nodes = number(),
+                "movetime" => limits.movetime = number().map(Duration::from_millis),
+                "wtime" => white = number().map(Duration::from_millis),
+                "btime" => black = number().map(Duration::from_millis),
+                "winc" => white_increment = number().map_or(Duration::ZERO, Duration::from_millis),
+                "binc" => black_increment = number().map_or(Duration::ZERO, Duration::from_millis),
+                "movestogo" => moves_to_go = number().map(|moves| moves as u32),
+                "infinite" => infinite = true,
+                _ => {}
+            }
+        }
+        let (time, increment) = match self.position.current_player {
+            PColor::White => (white, white_increment),
+            PColor::Black => (black, black_increment),
+        };
+        if let (None, Some(time), false) = (limits.movetime, time, infinite) {
+            limits.movetime = Some(allot(time, increment, moves_to_go));
+        }
+
+        self.stop.store(false, Ordering::Relaxed);
+        self.released.store(false, Ordering::Relaxed);
+        self.infinite = infinite;
+        let mut position = self.position.clone();
+        let engine = Arc::clone(&self.engine);
+        let output = Arc::clone(&self.output);
+        let released = Arc::clone(&self.released);
+        self.search = Some(thread::spawn(move || {
+            let mut engine = engine.lock().unwrap();
+            let best = engine.search_with(&mut position, limits, |info| {
+                send(&output, &info_line(info));
+            });
+            // an infinite search only gives its move when told to stop
+            while infinite && !released.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            let best = best.map_or(String::from("0000"), |mv| mv.to_string());
+            send(&output, &format!("bestmove {}", best));
+        }));
+    }
+
+    // "name <id> value <x>", the name being case insensitive
+    fn set_option(&mut self, args: &[&str]) {
+        let value_index = args
+            .iter()
+            .position(|word| *word == "value")
+            .unwrap_or(args.len());
+        let name = args
+            .get(1..value_index)
+            .unwrap_or_default()
+            .join(" ")
+            .to_lowercase();
+        let value = args.get(value_index + 1..).unwrap_or_default().join(" ");
+        match name.as_str() {
+            "hash" => match value.parse::<usize>() {
+                Ok(megabytes) => {
+                    self.stop();
+                    let megabytes = megabytes.clamp(1, MAX_HASH_MB);
+                    self.engine.lock().unwrap().set_hash_size(megabytes);
+                }
+                Err(_) => self.send(&format!("info string invalid Hash value '{}'", value)),
+            },
+            "threads" => {
+                if value != "1" {
+                    self.send("info string only one search thread is supported");
+                }
+            }
+            _ => self.send(&format!("info string unknown option '{}'", name)),
+        }
+    }
+
+    // ends the running search, which still gives its best move
+    fn stop(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            self.released.store(true, Ordering::Relaxed);
+            let _ = search.join();
+        }
+    }
+
+    // lets a limited search end by itself, as the input is over
+    fn finish(&mut self) {
+        if self.infinite {
+            self.stop();
+        } else if let Some(search) = self.search.take() {
+            let _ = search.join();
+        }
+    }
+}
+
+pub(crate) fn send<W: Write>(output: &Mutex<W>, line: &str) {
+    let mut output = output.lock().unwrap();
+    // nothing can be done when the GUI is gone
+    let _ = writeln!(output, "{}", line);
+    let _ = output.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // output kept by the test while the protocol writes to it
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn session(input: &str) -> Vec<String> {
+        let buffer = Buffer::default();
+        run(input.as_bytes(), buffer.clone());
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn introduces_itself() {
+        let lines = session("uci\nisready\nquit\n");
+        assert_eq!(lines[0], format!("id name {}", NAME));
+        assert!(lines.contains(&String::from(
+            "option name Hash type spin default 16 min 1 max 1024"
+        )));
+        assert_eq!(lines[lines.len() - 2..], ["uciok", "readyok"]);
+    }
+
+    #[test]
+    fn searches_the_given_position() {
+        let lines = session(
+            "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\n\
+             go depth 3\n",
+        );
+        let info = lines
+            .iter()
+            .find(|line| line.starts_with("info depth 2"))
+            .unwrap();
+        assert!(info.contains(" score mate 1 "));
+        assert!(info.ends_with(" pv a1a8"));
+        assert_eq!(lines.last().unwrap(), "bestmove a1a8");
+
+        // moves are played from the start position, black then mates
+        let lines = session("position startpos moves f2f3 e7e5 g2g4\ngo depth 2\n");
+        assert_eq!(lines.last().unwrap(), "bestmove d8h4");
+    }
+
+    #[test]
+    fn stops_when_told() {
+        let lines = session("position startpos\ngo infinite\nstop\nisready\n");
+        assert!(lines[lines.len() - 2].starts_with("bestmove "));
+        assert_eq!(lines.last().unwrap(), "readyok");
+
+        let lines = session("go wtime 300 btime 300\n");
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn reports_bad_input() {
+        let lines = session(
+            "position startpos moves e2e5\n\
+             position fen 8/8 w - - 0 1\n\
+             setoption name Hash value lots\n\
+             setoption name Hash value 1\n\
+             setoption name Threads value 4\n\
+             dance\n",
+        );
+        assert_eq!(lines.len(), 5);
+        assert!(lines.iter().all(|line| line.starts_with("info string ")));
+    }
+
+    #[test]
+    fn time_is_shared_between_the_moves() {
+        let ms = Duration::from_millis;
+        assert_eq!(allot(ms(60_000), ms(0), None), ms(2_000));
+        assert_eq!(allot(ms(60_000), ms(1_000), Some(10)), ms(6_750));
+        // little time left is never spent at once
+        assert_eq!(allot(ms(1_000), ms(2_000), None), ms(485));
+        assert_eq!(allot(ms(10), ms(0), None), ms(1));
+    }
+}
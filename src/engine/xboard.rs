@@ -0,0 +1,457 @@
+use super::search::{Engine, Limits, SearchInfo};
+use super::uci::{self, send, NAME};
+
+use crate::models::game::Game;
+use crate::models::piece::PColor;
+use crate::models::position::Position;
+
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// time given to a move when the interface sets no time control
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
+// mate scores as read by the interfaces, plus the number of moves
+const MATE_SCORE: i32 = 100_000;
+
+// Speaks the Chess Engine Communication Protocol used by XBoard and
+// WinBoard: the engine keeps the game, plays the color it is given and
+// answers the moves of the other side. Searches run on their own thread so
+// "?", "force" or "ping" are handled while thinking. Returns on "quit" or
+// at the end of the input, once the running search is over.
+pub fn run<R: BufRead, W: Write + Send + 'static>(input: R, output: W) {
+    let mut xboard = Xboard::new(output);
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if !xboard.handle(&line) {
+            xboard.cancel();
+            return;
+        }
+    }
+    xboard.finish();
+}
+
+// base time of a "level" command, in minutes or as "minutes:seconds"
+pub fn parse_base(text: &str) -> Option<Duration> {
+    let (minutes, seconds) = match text.split_once(':') {
+        None => (text, "0"),
+        Some(parts) => parts,
+    };
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: u64 = seconds.parse().ok()?;
+    Some(Duration::from_secs(minutes * 60 + seconds))
+}
+
+// thinking output: ply, score, time in centiseconds, nodes and the line
+// in standard algebraic notation
+pub fn thinking_line(info: &SearchInfo, root: &Position) -> String {
+    let score = match info.mate_in() {
+        Some(moves) if moves > 0 => MATE_SCORE + moves,
+        Some(moves) => -MATE_SCORE + moves,
+        None => info.score,
+    };
+    let mut position = root.clone();
+    let mut line = Vec::new();
+    for mv in &info.pv {
+        line.push(position.to_san(*mv));
+        position.make_move(*mv);
+    }
+    format!(
+        "{} {} {} {} {}",
+        info.depth,
+        score,
+        info.time.as_millis() / 10,
+        info.nodes,
+        line.join(" ")
+    )
+}
+
+// -------------------------------------------
+// ------------ PRIVATE FUNCTIONS ------------
+// -------------------------------------------
+
+struct Xboard<W> {
+    output: Arc<Mutex<W>>,
+    engine: Arc<Mutex<Engine>>,
+    // stop flag of the engine
+    stop: Arc<AtomicBool>,
+    // set when the move of the running search must not be played
+    cancelled: Arc<AtomicBool>,
+    game: Arc<Mutex<Game>>,
+    search: Option<JoinHandle<()>>,
+    // color played by the engine, None in force mode
+    engine_color: Option<PColor>,
+    // whether the thinking output is sent
+    post: bool,
+    // time control: moves per session (0 for the whole game), time of a
+    // session, increment, fixed time per move and depth limit
+    moves_per_session: u32,
+    session_time: Option<Duration>,
+    increment: Duration,
+    move_time: Option<Duration>,
+    depth: Option<u32>,
+    // time left on the engine's clock, as last told by the interface,
+    // the session time until then
+    time_left: Option<Duration>,
+}
+
+impl<W: Write + Send + 'static> Xboard<W> {
+    fn new(output: W) -> Xboard<W> {
+        let engine = Engine::new();
+        Xboard {
+            output: Arc::new(Mutex::new(output)),
+            stop: engine.stop_flag(),
+            engine: Arc::new(Mutex::new(engine)),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            game: Arc::new(Mutex::new(Game::new(Position::new(), None))),
+            search: None,
+            engine_color: Some(PColor::Black),
+            post: false,
+            moves_per_session: 0,
+            session_time: None,
+            increment: Duration::ZERO,
+            move_time: None,
+            depth: None,
+            time_left: None,
+        }
+    }
+
+    // returns false once the interface asks to quit
+    fn handle(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            None => return true,
+            Some((command, args)) => (*command, args),
+        };
+        let number = |index: usize| args.get(index).and_then(|value| value.parse::<u64>().ok());
+        match command {
+            "protover" => self.send(&format!(
+                "feature myname=\"{}\" setboard=1 usermove=1 ping=1 draw=0 san=0 \
+                 colors=0 analyze=0 sigint=0 sigterm=0 reuse=1 done=1",
+                NAME
+            )),
+            "new" => {
+                self.cancel();
+                *self.game.lock().unwrap() = Game::new(Position::new(), None);
+                self.engine.lock().unwrap().clear_hash();
+                self.engine_color = Some(PColor::Black);
+                self.depth = None;
+                self.time_left = None;
+            }
+            "setboard" => {
+                self.cancel();
+                match Position::from_fen(&args.join(" ")) {
+                    Ok(position) => *self.game.lock().unwrap() = Game::new(position, None),
+                    Err(_) => self.send("tellusererror Illegal position"),
+                }
+            }
+            "usermove" => match args.first() {
+                Some(text) => self.user_move(text),
+                None => self.send("Error (missing move): usermove"),
+            },
+            "go" => {
+                self.engine_color = Some(self.game.lock().unwrap().position.current_player);
+                self.think();
+            }
+            "force" | "result" => {
+                self.cancel();
+                self.engine_color = None;
+            }
+            "?" => self.move_now(),
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "level" => {
+                self.moves_per_session = number(0).unwrap_or(0) as u32;
+                self.session_time = args.get(1).and_then(|base| parse_base(base));
+                // seconds, possibly with a fraction
+                let increment = args
+                    .get(2)
+                    .and_then(|increment| increment.parse::<f64>().ok())
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+                self.increment = increment.unwrap_or(Duration::ZERO);
+                self.move_time = None;
+                self.time_left = None;
+                if self.session_time.is_none() || increment.is_none() {
+                    self.send(&format!("Error (bad time control): {}", line));
+                }
+            }
+            "st" => self.move_time = number(0).map(Duration::from_secs),
+            "sd" => self.depth = number(0).map(|depth| depth as u32),
+            // centiseconds
+            "time" => self.time_left = number(0).map(|time| Duration::from_millis(time * 10)),
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "ping" => self.send(&format!("pong {}", args.join(" "))),
+            "quit" => return false,
+            "xboard" | "accepted" | "rejected" | "draw" | "otim" | "random" | "hard" | "easy"
+            | "computer" | "name" | "rating" | "ics" | "white" | "black" => {}
+            // moves may come without "usermove" when the feature is refused
+            _ if self
+                .game
+                .lock()
+                .unwrap()
+                .position
+                .clone()
+                .parse_uci(command)
+                .is_ok() =>
+            {
+                self.user_move(command)
+            }
+            _ => self.send(&format!("Error (unknown command): {}", command)),
+        }
+        true
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.output, line);
+    }
+
+    fn user_move(&mut self, text: &str) {
+        self.cancel();
+        {
+            let mut game = self.game.lock().unwrap();
+            let mv = match game.position.parse_uci(text) {
+                Ok(mv) => mv,
+                Err(_) => {
+                    self.send(&format!("Illegal move: {}", text));
+                    return;
+                }
+            };
+            if !game.play_move(mv) {
+                self.send(&format!("Illegal move: {}", text));
+                return;
+            }
+            report_result(&mut game, &self.output);
+        }
+        self.think();
+    }
+
+    fn take_back(&mut self, count: usize) {
+        self.cancel();
+        let mut game = self.game.lock().unwrap();
+        for _ in 0..count {
+            game.undo();
+        }
+    }
+
+    // starts searching when the engine is to move, in place of any
+    // running search
+    fn think(&mut self) {
+        self.cancel();
+        let (mut position, limits) = {
+            let game = self.game.lock().unwrap();
+            if game.status.is_over() || self.engine_color != Some(game.position.current_player) {
+                return;
+            }
+            (game.position.clone(), self.limits(&game))
+        };
+
+        self.stop.store(false, Ordering::Relaxed);
+        self.cancelled.store(false, Ordering::Relaxed);
+        let engine = Arc::clone(&self.engine);
+        let output = Arc::clone(&self.output);
+        let game = Arc::clone(&self.game);
+        let cancelled = Arc::clone(&self.cancelled);
+        let post = self.post;
+        self.search = Some(thread::spawn(move || {
+            let root = position.clone();
+            let best = engine
+                .lock()
+                .unwrap()
+                .search_with(&mut position, limits, |info| {
+                    if post {
+                        send(&output, &thinking_line(info, &root));
+                    }
+                });
+            if cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Some(mv) = best {
+                let mut game = game.lock().unwrap();
+                if game.play_move(mv) {
+                    send(&output, &format!("move {}", mv));
+                    report_result(&mut game, &output);
+                }
+            }
+        }));
+    }
+
+    fn limits(&self, game: &Game) -> Limits {
+        let movetime = match (self.move_time, self.time_left.or(self.session_time)) {
+            (Some(time), _) => Some(time),
+            (None, Some(left)) => {
+                // moves of the engine since the game was set up, the
+                // players moving in turn
+                let played = (game.moves.len() / 2) as u32;
+                let moves_to_go = match self.moves_per_session {
+                    0 => None,
+                    session => Some(session - played % session),
+                };
+                Some(uci::allot(left, self.increment, moves_to_go))
+            }
+            // a depth limit alone bounds the search
+            (None, None) if self.depth.is_some() => None,
+            (None, None) => Some(DEFAULT_MOVE_TIME),
+        };
+        Limits {
+            depth: self.depth,
+            movetime,
+            nodes: None,
+        }
+    }
+
+    // ends the search at once, its best move being played
+    fn move_now(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            let _ = search.join();
+        }
+    }
+
+    // ends the search without playing its move
+    fn cancel(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.move_now();
+    }
+
+    // lets the search end by itself, as the input is over
+    fn finish(&mut self) {
+        if let Some(search) = self.search.take() {
+            let _ = search.join();
+        }
+    }
+}
+
+// announces the end of the game, claiming the draws the rules allow
+fn report_result<W: Write>(game: &mut Game, output: &Mutex<W>) {
+    game.claim_draw();
+    if game.status.is_over() {
+        send(
+            output,
+            &format!("{} {{{}}}", game.status.result(), game.status),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // output kept by the test while the protocol writes to it
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn session(input: &str) -> Vec<String> {
+        let buffer = Buffer::default();
+        run(input.as_bytes(), buffer.clone());
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn announces_its_features() {
+        let lines = session("xboard\nprotover 2\ndraw\nping 7\nquit\n");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(&format!("feature myname=\"{}\"", NAME)));
+        assert!(lines[0].contains(" draw=0 ") && lines[0].ends_with(" done=1"));
+        assert_eq!(lines[1], "pong 7");
+    }
+
+    #[test]
+    fn plays_and_reports_the_result() {
+        // the engine, black after "new", is given the fool's mate
+        let lines = session("new\nforce\nusermove f2f3\nusermove e7e5\nusermove g2g4\nsd 2\ngo\n");
+        assert_eq!(lines, ["move d8h4", "0-1 {Black wins by checkmate}"]);
+
+        // with the thinking output
+        let lines =
+            session("new\nforce\nsetboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\npost\nsd 2\ngo\n");
+        assert!(lines[0].starts_with("1 "));
+        assert!(lines[1].starts_with("2 100001 ") && lines[1].ends_with(" Ra8#"));
+        assert_eq!(lines[2..], ["move a1a8", "1-0 {White wins by checkmate}"]);
+
+        // the engine claims the draws it may
+        let lines =
+            session("new\nforce\nsetboard 4k3/8/8/8/8/8/8/R3K3 w - - 99 80\nusermove a1a2\n");
+        assert_eq!(lines, ["1/2-1/2 {Draw by the fifty-move rule}"]);
+    }
+
+    #[test]
+    fn plays_a_single_move_per_turn() {
+        let lines = session("new\nforce\nst 1\ngo\ngo\ngo\n");
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("move "));
+    }
+
+    #[test]
+    fn moves_can_be_taken_back() {
+        let lines = session("new\nforce\nusermove e2e4\nundo\nusermove e7e5\n");
+        assert_eq!(lines, ["Illegal move: e7e5"]);
+        let lines = session("new\nforce\ne2e4\ne7e5\nremove\nusermove e7e5\nusermove e2e5\n");
+        assert_eq!(lines, ["Illegal move: e7e5", "Illegal move: e2e5"]);
+    }
+
+    #[test]
+    fn reports_bad_input() {
+        let lines = session("setboard 8/8 w - - 0 1\nlevel 40 x 0\nlevel 40 5 x\ndance\n");
+        assert_eq!(
+            lines,
+            [
+                "tellusererror Illegal position",
+                "Error (bad time control): level 40 x 0",
+                "Error (bad time control): level 40 5 x",
+                "Error (unknown command): dance",
+            ]
+        );
+    }
+
+    #[test]
+    fn time_controls() {
+        assert_eq!(parse_base("5"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_base("0:30"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_base("x"), None);
+
+        let mut xboard = Xboard::new(Buffer::default());
+        let movetime = |xboard: &Xboard<Buffer>| {
+            let game = xboard.game.lock().unwrap();
+            xboard.limits(&game).movetime
+        };
+        assert_eq!(movetime(&xboard), Some(DEFAULT_MOVE_TIME));
+        xboard.handle("sd 4");
+        assert_eq!(movetime(&xboard), None);
+        xboard.handle("st 3");
+        assert_eq!(movetime(&xboard), Some(Duration::from_secs(3)));
+        // 40 moves in 5 minutes, then with 60 seconds left for all of them
+        xboard.handle("level 40 5 0");
+        assert_eq!(movetime(&xboard), Some(Duration::from_millis(7_500)));
+        xboard.handle("time 6000");
+        assert_eq!(movetime(&xboard), Some(Duration::from_millis(1_500)));
+
+        // the session counts the moves from the position set up
+        xboard.handle("force");
+        xboard.handle("setboard 4k3/8/8/8/8/8/4P3/4K3 w - - 0 37");
+        assert_eq!(movetime(&xboard), Some(Duration::from_millis(1_500)));
+        xboard.handle("usermove e2e4");
+        xboard.handle("usermove e8d8");
+        assert_eq!(movetime(&xboard), Some(Duration::from_secs(60) / 39));
+
+        // increments may have a fraction of a second
+        xboard.handle("level 0 5 0.5");
+        assert_eq!(movetime(&xboard), Some(Duration::from_millis(10_375)));
+    }
+}
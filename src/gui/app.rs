@@ -0,0 +1,514 @@
+use super::canvas_display;
+use super::sound::Sound;
+use super::textures::PieceTextures;
+
+use drwchess::engine::search::{Engine, Limits};
+use drwchess::models::game::Game;
+use drwchess::models::piece::{PColor, Piece, PieceType};
+use drwchess::models::position::Position;
+use drwchess::models::r#move::Move;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+// search of the computer running on its own thread, stopped as soon as
+// its result is no longer wanted
+pub struct Thinking {
+    // ply the search started at
+    pub ply: usize,
+    pub receiver: Receiver<Option<Move>>,
+    pub stop: Arc<AtomicBool>,
+}
+
+impl Drop for Thinking {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// window frontend of a game: draws it and turns mouse events into moves
+pub struct App<'a> {
+    pub game: Game,
+    // textures used to display the pieces on the board
+    pub piece_textures: PieceTextures<'a>,
+    // piece states, used in order to know which piece is currently being
+    // hold by the player, the piece stays on its square until it is played
+    pub piece_hold: Option<Piece>,
+    pub x: usize,
+    pub y: usize,
+    // square a held pawn was dropped on, waiting for the player to pick
+    // the piece it is promoted to
+    pub pending_promotion: Option<usize>,
+    // earlier ply shown while browsing the history, with its position, the
+    // board cannot be played on until the live position is shown again
+    pub view: Option<(usize, Position)>,
+    // color played by the computer, if any, how long it may think, and
+    // its running search, stopped when dropped
+    pub computer: Option<PColor>,
+    pub limits: Limits,
+    pub thinking: Option<Thinking>,
+    // colors of the squares
+    pub white: Color,
+    pub black: Color,
+}
+
+impl App<'_> {
+    pub fn new(renderer: &TextureCreator<WindowContext>, game: Game) -> App<'_> {
+        // the given position may already be over
+        if game.status.is_over() {
+            println!("{}", game.status);
+        }
+        App {
+            game,
+            piece_textures: PieceTextures::new(renderer),
+            piece_hold: None,
+            x: 0,
+            y: 0,
+            pending_promotion: None,
+            view: None,
+            computer: None,
+            limits: Limits::default(),
+            thinking: None,
+            white: Color::RGBA(234, 203, 164, 255),
+            black: Color::RGBA(185, 112, 68, 255),
+        }
+    }
+
+    pub fn select_piece(&mut self, x: i32, y: i32, width: u32, height: u32) {
+        // the computer's pieces are left alone while it thinks
+        if self.game.status.is_over()
+            || self.pending_promotion.is_some()
+            || self.view.is_some()
+            || self.is_computer_turn()
+        {
+            return;
+        }
+        let size = self.game.position.board.size;
+        let i: usize = size * y as usize / height as usize;
+        let j: usize = size * x as usize / width as usize;
+
+        let selected: Option<Piece> = self.game.position.board.get(i, j);
+        println!("found coordinate: ({}, {})", j, i);
+        match selected {
+            Some(p) if p.color == self.game.position.current_player => {
+                self.piece_hold = selected;
+                self.x = j;
+                self.y = i;
+                println!("x: {}, y: {}", self.x, self.y);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn make_move(&mut self, x: i32, y: i32, width: u32, height: u32, sound: &Sound) {
+        if self.piece_hold.is_none() || self.is_computer_turn() {
+            return;
+        }
+        let size = self.game.position.board.size;
+        let i: usize = size * y as usize / height as usize;
+        let j: usize = size * x as usize / width as usize;
+
+        let start: usize = self.y * size + self.x;
+        let end: usize = i * size + j;
+
+        if let Some(target) = self.pending_promotion {
+            self.choose_promotion(start, target, end, sound);
+            return;
+        }
+
+        if self.game.is_promotion(start, end) {
+            // the move is only played once the new piece has been picked
+            self.pending_promotion = Some(end);
+            return;
+        }
+        self.play_move(start, end, None, sound);
+    }
+
+    pub fn resign(&mut self, sound: &Sound) {
+        if self.game.resign() {
+            self.announce_end(sound);
+        }
+    }
+
+    pub fn claim_draw(&mut self, sound: &Sound) {
+        if self.game.claim_draw() {
+            self.announce_end(sound);
+        }
+    }
+
+    // flags the player to move once their time is over, to be called every frame
+    pub fn update_clock(&mut self, sound: &Sound) {
+        if self.game.update_clock() {
+            self.announce_end(sound);
+        }
+    }
+
+    // takes back the last move, showing the live position again; against
+    // the computer its reply is taken back too
+    pub fn undo(&mut self, sound: &Sound) {
+        self.view = None;
+        self.thinking = None;
+        self.reset_hold_piece_states();
+        while let Some(mv) = self.game.undo() {
+            println!("Took back {}", mv);
+            sound.play("move");
+            if !self.is_computer_turn() {
+                break;
+            }
+        }
+    }
+
+    pub fn redo(&mut self, sound: &Sound) {
+        self.view = None;
+        self.thinking = None;
+        self.reset_hold_piece_states();
+        while let Some(mv) = self.game.redo() {
+            println!("Played again {}", mv);
+            self.play_sound(mv, sound);
+            if !self.is_computer_turn() {
+                break;
+            }
+        }
+    }
+
+    // starts the computer thinking when it is its turn and plays the move
+    // once found, to be called every frame
+    pub fn update_computer(&mut self, sound: &Sound) {
+        if let Some(thinking) = &self.thinking {
+            let found = match thinking.receiver.try_recv() {
+                Err(TryRecvError::Empty) => return,
+                // a move taken back or the game ending meanwhile makes the
+                // result useless
+                Ok(Some(mv))
+                    if thinking.ply == self.game.moves.len() && !self.game.status.is_over() =>
+                {
+                    Some(mv)
+                }
+                _ => None,
+            };
+            self.thinking = None;
+            if let Some(mv) = found {
+                self.play(mv, sound);
+            }
+            return;
+        }
+        if !self.is_computer_turn() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let mut position = self.game.position.clone();
+        let limits = self.limits;
+        let mut engine = Engine::new();
+        let stop = engine.stop_flag();
+        thread::spawn(move || {
+            let mv = engine.search(&mut position, limits);
+            // the receiver is gone when the game moved on
+            let _ = sender.send(mv);
+        });
+        self.thinking = Some(Thinking {
+            ply: self.game.moves.len(),
+            receiver,
+            stop,
+        });
+    }
+
+    // history navigation, going past the last move shows the live position
+    pub fn step_back(&mut self) {
+        self.show_ply(self.shown_ply().saturating_sub(1));
+    }
+
+    pub fn step_forward(&mut self) {
+        self.show_ply(self.shown_ply() + 1);
+    }
+
+    pub fn go_to_start(&mut self) {
+        self.show_ply(0);
+    }
+
+    pub fn go_to_end(&mut self) {
+        self.show_ply(self.game.moves.len());
+    }
+
+    pub fn title(&self) -> String {
+        let title = self.game.title();
+        match &self.view {
+            None => title,
+            Some((ply, _)) => format!("{} - Move {} of {}", title, ply, self.game.moves.len()),
+        }
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut WindowCanvas,
+        width: i32,
+        height: i32,
+        mouse_x: i32,
+        mouse_y: i32,
+    ) {
+        self.draw_board(canvas, width, height);
+        self.draw_last_move(canvas, width, height);
+        self.draw_possible_moves(canvas, width, height);
+        self.draw_pieces(canvas, width, height);
+        self.draw_promotion(canvas, width, height);
+        self.draw_hold(canvas, width, height, mouse_x, mouse_y);
+    }
+
+    // -------------------------------------------
+    // ------------ PRIVATE FUNCTIONS ------------
+    // -------------------------------------------
+
+    fn announce_end(&mut self, sound: &Sound) {
+        self.thinking = None;
+        self.reset_hold_piece_states();
+        println!("{}", self.game.status);
+        sound.play("game_over");
+    }
+
+    fn shown_ply(&self) -> usize {
+        match &self.view {
+            None => self.game.moves.len(),
+            Some((ply, _)) => *ply,
+        }
+    }
+
+    fn show_ply(&mut self, ply: usize) {
+        self.reset_hold_piece_states();
+        self.view = if ply >= self.game.moves.len() {
+            None
+        } else {
+            Some((ply, self.game.position_at(ply)))
+        };
+    }
+
+    fn shown_position(&self) -> &Position {
+        match &self.view {
+            None => &self.game.position,
+            Some((_, position)) => position,
+        }
+    }
+
+    // move that led to the shown position
+    fn shown_last_move(&self) -> Option<Move> {
+        match &self.view {
+            None => self.game.last_move,
+            Some((0, _)) => None,
+            Some((ply, _)) => Some(self.game.moves[ply - 1].mv),
+        }
+    }
+
+    // square the held piece was picked from
+    fn hold_square(&self) -> Option<usize> {
+        self.piece_hold?;
+        Some(self.y * self.game.position.board.size + self.x)
+    }
+
+    fn square_rect(&self, square: usize, width: i32, height: i32) -> Rect {
+        let size = self.game.position.board.size;
+        let case_height: i32 = height / size as i32;
+        let case_width: i32 = width / size as i32;
+
+        Rect::new(
+            (square % size) as i32 * case_width,
+            (square / size) as i32 * case_height,
+            case_width as u32,
+            case_height as u32,
+        )
+    }
+
+    fn draw_board(&self, canvas: &mut WindowCanvas, width: i32, height: i32) {
+        let size = self.game.position.board.size;
+        for square in 0..size * size {
+            // a8 is a light square
+            if (square / size + square % size).is_multiple_of(2) {
+                canvas.set_draw_color(self.white);
+            } else {
+                canvas.set_draw_color(self.black);
+            }
+            canvas_display::canvas_fill(canvas, self.square_rect(square, width, height));
+        }
+    }
+
+    fn draw_pieces(&self, canvas: &mut WindowCanvas, width: i32, height: i32) {
+        let hold = self.hold_square();
+        for (square, piece) in self.shown_position().board.squares().iter().enumerate() {
+            if let Some(p) = piece {
+                // the held piece follows the mouse instead
+                if Some(square) == hold {
+                    continue;
+                }
+                canvas_display::canvas_copy(
+                    canvas,
+                    self.piece_textures.get_piece(*p),
+                    None,
+                    Some(self.square_rect(square, width, height)),
+                );
+            }
+        }
+    }
+
+    fn draw_hold(
+        &self,
+        canvas: &mut WindowCanvas,
+        width: i32,
+        height: i32,
+        mouse_x: i32,
+        mouse_y: i32,
+    ) {
+        if self.pending_promotion.is_some() {
+            return;
+        }
+        if let Some(p) = self.piece_hold {
+            let size = self.game.position.board.size;
+            let case_height: i32 = height / size as i32;
+            let case_width: i32 = width / size as i32;
+
+            let rect = Rect::new(
+                mouse_x - case_width / 2,
+                mouse_y - case_height / 2,
+                case_width as u32,
+                case_height as u32,
+            );
+            canvas_display::canvas_copy(canvas, self.piece_textures.get_piece(p), None, Some(rect));
+        }
+    }
+
+    fn draw_last_move(&self, canvas: &mut WindowCanvas, width: i32, height: i32) {
+        if let Some(m) = self.shown_last_move() {
+            canvas.set_draw_color(Color::RGBA(0, 255, 0, 30));
+            canvas_display::canvas_fill(canvas, self.square_rect(m.start(), width, height));
+            canvas.set_draw_color(Color::RGBA(255, 255, 0, 30));
+            canvas_display::canvas_fill(canvas, self.square_rect(m.end(), width, height));
+        }
+    }
+
+    fn draw_possible_moves(&self, canvas: &mut WindowCanvas, width: i32, height: i32) {
+        if self.pending_promotion.is_some() {
+            return;
+        }
+        let square = match self.hold_square() {
+            None => return,
+            Some(square) => square,
+        };
+
+        canvas.set_draw_color(Color::RGBA(255, 0, 0, 200));
+        for mv in self.game.possible_moves(square) {
+            canvas_display::canvas_fill(canvas, self.square_rect(mv.end(), width, height));
+        }
+    }
+
+    fn play_move(&mut self, start: usize, end: usize, promotion: Option<PieceType>, sound: &Sound) {
+        println!("start given: {}, end given: {}", start, end);
+        let mv = match self.game.find_move(start, end, promotion) {
+            None => {
+                self.reset_hold_piece_states();
+                return;
+            }
+            Some(mv) => mv,
+        };
+
+        self.play(mv, sound);
+    }
+
+    fn play(&mut self, mv: Move, sound: &Sound) {
+        let san = self.game.position.to_san(mv);
+        self.reset_hold_piece_states();
+        if !self.game.play_move(mv) {
+            return;
+        }
+        println!("{}", san);
+        self.play_sound(mv, sound);
+    }
+
+    fn is_computer_turn(&self) -> bool {
+        !self.game.status.is_over() && self.computer == Some(self.game.position.current_player)
+    }
+
+    // sound of the move just played
+    fn play_sound(&self, mv: Move, sound: &Sound) {
+        if self.game.status.is_over() {
+            println!("{}", self.game.status);
+            sound.play("game_over");
+        } else if self.game.is_in_check() {
+            sound.play("check");
+        } else if mv.is_castle() {
+            sound.play("castle");
+        } else if mv.is_capture() {
+            sound.play("take");
+        } else {
+            sound.play("move");
+        }
+    }
+
+    fn choose_promotion(&mut self, start: usize, target: usize, clicked: usize, sound: &Sound) {
+        self.pending_promotion = None;
+        let choice = self
+            .promotion_choices(target)
+            .into_iter()
+            .find(|(square, _)| *square == clicked);
+        match choice {
+            Some((_, r#type)) => self.play_move(start, target, Some(r#type), sound),
+            // clicking anywhere else cancels the promotion
+            None => self.reset_hold_piece_states(),
+        }
+    }
+
+    // squares of the picker, starting from the promotion square towards the
+    // center of the board, with the piece each of them stands for
+    fn promotion_choices(&self, target: usize) -> Vec<(usize, PieceType)> {
+        let size = self.game.position.board.size;
+        let row: usize = target / size;
+        let column: usize = target % size;
+        [
+            PieceType::Queen,
+            PieceType::Knight,
+            PieceType::Rook,
+            PieceType::Bishop,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, r#type)| {
+            let y = if row == 0 { index } else { row - index };
+            (y * size + column, r#type)
+        })
+        .collect()
+    }
+
+    fn draw_promotion(&self, canvas: &mut WindowCanvas, width: i32, height: i32) {
+        let target = match self.pending_promotion {
+            None => return,
+            Some(t) => t,
+        };
+
+        // the rest of the board is dimmed while the player is picking
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 150));
+        canvas_display::canvas_fill(canvas, Rect::new(0, 0, width as u32, height as u32));
+
+        let color = self.game.position.current_player;
+        for (square, r#type) in self.promotion_choices(target) {
+            let rect = self.square_rect(square, width, height);
+            canvas.set_draw_color(Color::RGBA(240, 240, 240, 255));
+            canvas_display::canvas_fill(canvas, rect);
+            canvas_display::canvas_copy(
+                canvas,
+                self.piece_textures.get(color, r#type),
+                None,
+                Some(rect),
+            );
+        }
+    }
+
+    fn reset_hold_piece_states(&mut self) {
+        self.piece_hold = None;
+        self.pending_promotion = None;
+        self.x = 0;
+        self.y = 0;
+    }
+}
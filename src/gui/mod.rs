@@ -0,0 +1,4 @@
+pub mod app;
+pub mod canvas_display;
+pub mod sound;
+pub mod textures;
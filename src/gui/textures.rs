@@ -0,0 +1,65 @@
+use drwchess::models::piece::{PColor, Piece, PieceType};
+
+use sdl2::image::LoadTexture;
+use sdl2::render::Texture;
+use sdl2::render::TextureCreator;
+use sdl2::video::WindowContext;
+
+use std::collections::HashMap;
+
+pub struct PieceTextures<'a> {
+    pub black_textures: HashMap<PieceType, Texture<'a>>,
+    pub white_textures: HashMap<PieceType, Texture<'a>>,
+}
+
+impl PieceTextures<'_> {
+    pub fn new(renderer: &TextureCreator<WindowContext>) -> PieceTextures<'_> {
+        let tmp_black = HashMap::from([
+            (PieceType::Pawn, "textures/pieces/black_pawn.png"),
+            (PieceType::Knight, "textures/pieces/black_knight.png"),
+            (PieceType::Bishop, "textures/pieces/black_bishop.png"),
+            (PieceType::Rook, "textures/pieces/black_rook.png"),
+            (PieceType::Queen, "textures/pieces/black_queen.png"),
+            (PieceType::King, "textures/pieces/black_king.png"),
+        ]);
+
+        let tmp_white = HashMap::from([
+            (PieceType::Pawn, "textures/pieces/white_pawn.png"),
+            (PieceType::Knight, "textures/pieces/white_knight.png"),
+            (PieceType::Bishop, "textures/pieces/white_bishop.png"),
+            (PieceType::Rook, "textures/pieces/white_rook.png"),
+            (PieceType::Queen, "textures/pieces/white_queen.png"),
+            (PieceType::King, "textures/pieces/white_king.png"),
+        ]);
+
+        let mut white: HashMap<PieceType, Texture> = HashMap::new();
+        let mut black: HashMap<PieceType, Texture> = HashMap::new();
+
+        for (piece, path) in tmp_black {
+            black.insert(piece, renderer.load_texture(path).unwrap());
+            println!("Loaded piece at path {}", path);
+        }
+
+        for (piece, path) in tmp_white {
+            white.insert(piece, renderer.load_texture(path).unwrap());
+            println!("Loaded piece at path {}", path);
+        }
+
+        PieceTextures {
+            black_textures: black,
+            white_textures: white,
+        }
+    }
+
+    pub fn get(&self, color: PColor, r#type: PieceType) -> &Texture<'_> {
+        let textures = match color {
+            PColor::White => &self.white_textures,
+            PColor::Black => &self.black_textures,
+        };
+        textures.get(&r#type).unwrap()
+    }
+
+    pub fn get_piece(&self, piece: Piece) -> &Texture<'_> {
+        self.get(piece.color, piece.r#type)
+    }
+}
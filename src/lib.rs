@@ -0,0 +1,5 @@
+// chess core: board, pieces, moves, rules and notation, without any
+// rendering so it can be used by tools, servers and tests
+pub mod common;
+pub mod engine;
+pub mod models;
@@ -1,24 +1,44 @@
-mod common;
-mod models;
+mod gui;
 
 extern crate dotenv;
 
-use models::game::Game;
-use models::sound::Sound;
+use drwchess::engine::search::Limits;
+use drwchess::models::clock::Clock;
+use drwchess::models::fen;
+use drwchess::models::game::Game;
+use drwchess::models::perft;
+use drwchess::models::pgn::{self, Header};
+use drwchess::models::piece::PColor;
+use drwchess::models::position::Position;
+use drwchess::models::r#move::MoveGenerator;
+
+use gui::app::App;
+use gui::sound::Sound;
 
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::mixer::{InitFlag, AUDIO_S32LSB, DEFAULT_CHANNELS};
 use sdl2::mouse::MouseButton;
 use sdl2::pixels::Color;
 use sdl2::render::BlendMode;
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 static WIDTH: u32 = 800;
 static HEIGHT: u32 = 800;
 
+// either control key
+const CTRL: Mod = Mod::LCTRLMOD.union(Mod::RCTRLMOD);
+
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // "drwchess perft <depth> [fen]" counts moves without opening a window
+    if args.get(1).map(|arg| arg.as_str()) == Some("perft") {
+        run_perft(&args[2..]);
+        return;
+    }
+
     let sdl_context = sdl2::init().unwrap();
 
     // ------------------------------------------
@@ -63,7 +83,63 @@ fn main() {
     // ------------ GAME COMPONENTS -------------
     // ------------------------------------------
 
-    let mut game: Game = Game::new(&renderer);
+    // optional time control given as "--clock minutes+increment"
+    let clock: Option<Clock> = match option_value(&args, "--clock") {
+        None => None,
+        Some(control) => {
+            let clock = Clock::from_control(control);
+            if clock.is_none() {
+                println!(
+                    "Error: invalid time control '{}', playing without clock",
+                    control
+                );
+            }
+            clock
+        }
+    };
+
+    // optional starting position given as "--fen <notation>"
+    let position: Position = match option_value(&args, "--fen") {
+        None => Position::new(),
+        Some(notation) => match Position::from_fen(notation) {
+            Ok(position) => position,
+            Err(msg) => {
+                println!("Error: invalid FEN '{}': {}", notation, msg);
+                Position::new()
+            }
+        },
+    };
+
+    // optional file the game is saved to as PGN, given as "--pgn <file>"
+    let pgn_file: Option<&str> = option_value(&args, "--pgn");
+
+    // optional game to continue, given as "--load <file>" with "--game <n>"
+    // picking a game other than the first of the file
+    let game: Game = match option_value(&args, "--load") {
+        None => Game::new(position, clock),
+        Some(path) => match load_pgn(path, option_value(&args, "--game")) {
+            Some(mut game) => {
+                game.set_clock(clock);
+                game
+            }
+            None => Game::new(position, clock),
+        },
+    };
+
+    let mut app: App = App::new(&renderer, game);
+
+    // optional game against the computer, given as "--play white|black"
+    // with the color of the human player, the computer thinking for
+    // "--movetime <ms>" (one second by default) or down to "--depth <n>"
+    if let Some(color) = option_value(&args, "--play") {
+        match color.parse::<PColor>() {
+            Ok(color) => {
+                app.computer = Some(color.opposite());
+                app.limits = computer_limits(&args);
+            }
+            Err(msg) => println!("Error: {}, playing without computer", msg),
+        }
+    }
     let sound: Sound = Sound::new();
     sound.play("starting_game");
 
@@ -85,13 +161,75 @@ fn main() {
                 } => {
                     break 'running;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    ..
+                } => {
+                    app.resign(&sound);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::D),
+                    ..
+                } => {
+                    app.claim_draw(&sound);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    ..
+                } => {
+                    println!("{}", app.game.position.to_fen());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Z),
+                    keymod,
+                    ..
+                } if keymod.intersects(CTRL) => {
+                    app.undo(&sound);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Y),
+                    keymod,
+                    ..
+                } if keymod.intersects(CTRL) => {
+                    app.redo(&sound);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Left),
+                    ..
+                } => {
+                    app.step_back();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Right),
+                    ..
+                } => {
+                    app.step_forward();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Home | Keycode::Up),
+                    ..
+                } => {
+                    app.go_to_start();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::End | Keycode::Down),
+                    ..
+                } => {
+                    app.go_to_end();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::S),
+                    ..
+                } => {
+                    save_pgn(&app.game, pgn_file);
+                }
                 Event::MouseButtonDown {
                     mouse_btn: MouseButton::Left,
                     x,
                     y,
                     ..
                 } => {
-                    game.select_piece(x, y, WIDTH, HEIGHT);
+                    app.select_piece(x, y, WIDTH, HEIGHT);
                 }
                 Event::MouseButtonUp {
                     mouse_btn: MouseButton::Left,
@@ -99,7 +237,7 @@ fn main() {
                     y,
                     ..
                 } => {
-                    game.make_move(x, y, WIDTH, HEIGHT, &sound);
+                    app.make_move(x, y, WIDTH, HEIGHT, &sound);
                 }
                 Event::MouseMotion { x, y, .. } => {
                     mouse_x = x;
@@ -108,12 +246,138 @@ fn main() {
                 _ => {}
             }
         }
+        app.update_clock(&sound);
+        app.update_computer(&sound);
+        let title = app.title();
+        if canvas.window().title() != title {
+            if let Err(msg) = canvas.window_mut().set_title(&title) {
+                println!("Error: {}", msg);
+            }
+        }
+
         canvas.set_draw_color(Color::RGBA(0, 0, 0, 255));
         canvas.clear();
-        game.draw(&mut canvas, WIDTH as i32, HEIGHT as i32, mouse_x, mouse_y);
+        app.draw(&mut canvas, WIDTH as i32, HEIGHT as i32, mouse_x, mouse_y);
 
         canvas.present();
         // Time management
         ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
     }
+
+    // the game given a file is kept when the window closes
+    if pgn_file.is_some() {
+        save_pgn(&app.game, pgn_file);
+    }
+}
+
+fn computer_limits(args: &[String]) -> Limits {
+    let number = |name: &str| -> Option<u64> {
+        let value = option_value(args, name)?;
+        let number = value.parse().ok();
+        if number.is_none() {
+            println!("Error: invalid value '{}' for {}", value, name);
+        }
+        number
+    };
+    let depth = number("--depth").map(|depth| depth as u32);
+    let movetime = match number("--movetime") {
+        Some(millis) => Some(Duration::from_millis(millis)),
+        None if depth.is_none() => Some(Duration::from_secs(1)),
+        None => None,
+    };
+    Limits {
+        depth,
+        movetime,
+        nodes: None,
+    }
+}
+
+// main line of a game of the file, errors being printed
+fn load_pgn(path: &str, number: Option<&str>) -> Option<Game> {
+    let number: usize = match number.map(|number| number.parse()) {
+        None => 1,
+        Some(Ok(number)) => number,
+        Some(Err(_)) => {
+            println!("Error: invalid game number, loading the first game");
+            1
+        }
+    };
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(msg) => {
+            println!("Error: could not read {}: {}", path, msg);
+            return None;
+        }
+    };
+    match pgn::parse(&text) {
+        Err(msg) => {
+            println!("Error: {}", msg);
+            None
+        }
+        Ok(games) => match games.get(number.wrapping_sub(1)) {
+            None => {
+                println!("Error: {} holds {} game(s)", path, games.len());
+                None
+            }
+            Some(game) => Some(game.replay()),
+        },
+    }
+}
+
+// writes the game to the file, or to a new file named after the current
+// time when none is given
+fn save_pgn(game: &Game, file: Option<&str>) {
+    let path = match file {
+        Some(path) => String::from(path),
+        None => chrono::Local::now()
+            .format("drwchess-%Y%m%d-%H%M%S.pgn")
+            .to_string(),
+    };
+    let text = pgn::write(game, &Header::new(), game.clock.is_some());
+    match std::fs::write(&path, text) {
+        Ok(()) => println!("Game saved to {}", path),
+        Err(msg) => println!("Error: could not save the game to {}: {}", path, msg),
+    }
+}
+
+fn option_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let index = args.iter().position(|arg| arg == name)?;
+    args.get(index + 1).map(|value| value.as_str())
+}
+
+fn run_perft(args: &[String]) {
+    let depth: u32 = match args.first().map(|depth| depth.parse()) {
+        Some(Ok(depth)) => depth,
+        _ => {
+            println!("Usage: drwchess perft <depth> [fen]");
+            return;
+        }
+    };
+    let notation = if args.len() > 1 {
+        args[1..].join(" ")
+    } else {
+        String::from(fen::START_POSITION)
+    };
+    let mut position = match Position::from_fen(&notation) {
+        Ok(position) => position,
+        Err(msg) => {
+            println!("Error: invalid FEN '{}': {}", notation, msg);
+            return;
+        }
+    };
+
+    let generator = MoveGenerator::new();
+    let start = Instant::now();
+    let split = perft::divide(&mut position, &generator, depth);
+    for (mv, nodes) in &split {
+        println!("{}: {}", mv, nodes);
+    }
+    // at depth 0 the position itself is the only node
+    let total: u64 = match depth {
+        0 => 1,
+        _ => split.iter().map(|(_, nodes)| nodes).sum(),
+    };
+    println!();
+    println!("Nodes searched: {}", total);
+    println!("Time: {} ms", start.elapsed().as_millis());
 }
@@ -0,0 +1,424 @@
+use crate::common::move_data::{precomputed_move_data, DIRECTION_OFFSET};
+
+use super::piece::PColor;
+
+use std::sync::OnceLock;
+
+// A bitboard is a set of squares held in a single integer, bit n standing
+// for square n (a8 = 0, h1 = 63)
+pub type Bitboard = u64;
+
+// a8 and every square of the same color
+pub const LIGHT_SQUARES: Bitboard = 0xaa55_aa55_aa55_aa55;
+
+pub fn bit(square: usize) -> Bitboard {
+    1 << square
+}
+
+// squares of the set, from a8 to h1
+pub fn squares(mut set: Bitboard) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if set == 0 {
+            return None;
+        }
+        let square = set.trailing_zeros() as usize;
+        set &= set - 1;
+        Some(square)
+    })
+}
+
+// Sliding attacks are looked up with magic bitboards: the relevant blockers
+// of a square are multiplied by a magic number so that their top bits give
+// an index in a table of precomputed attacks
+struct Magic {
+    mask: Bitboard,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: Bitboard) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+// magic numbers of each square, found once by trying random sparse numbers
+// until every blocker set of the square mapped to a slot holding its attacks
+const ROOK_MAGICS: [u64; 64] = [
+    0x1080004008801020,
+    0x0840092002c03000,
+    0x1900200010400900,
+    0x0880100008000480,
+    0x4200100420080200,
+    0x8100020100080400,
+    0x0200040110886200,
+    0x0200008040220411,
+    0x0404800084400220,
+    0x0000401000402000,
+    0x0086001081220440,
+    0x0408800800100280,
+    0x000a001201040820,
+    0x8848800200840080,
+    0x4001000100040200,
+    0x0442000102105084,
+    0x9080010020804100,
+    0x0040404000201009,
+    0x0000808010002009,
+    0x2200090021d00100,
+    0x0008008008040080,
+    0x0004004002010040,
+    0x0011040008015042,
+    0x00000a0001768104,
+    0x0000800080204009,
+    0x2010004140002001,
+    0x9800200280100080,
+    0x1000100080080080,
+    0x0442000a00049020,
+    0x2100040080020080,
+    0x0800120400900148,
+    0x0010040a00128541,
+    0x2800804000800030,
+    0x1010002000400041,
+    0x4000200011004100,
+    0x0610008410800800,
+    0x0400802402800800,
+    0xc100020080800400,
+    0x0002000802000401,
+    0x0182085882000401,
+    0x0220204000808000,
+    0x2860100040024022,
+    0x0001002004110040,
+    0x99101042000a0020,
+    0x0004080004008080,
+    0x0010040002008080,
+    0x2012004881020004,
+    0x8300842444820011,
+    0x0088403882010200,
+    0x0820400080210100,
+    0x0110910040a00300,
+    0x0801100280080480,
+    0x0242009008200600,
+    0x1002000489500200,
+    0x0040800200010080,
+    0x0091800041000080,
+    0x0000209300488001,
+    0x04c1002414824001,
+    0x020020000b001041,
+    0x7000100004200901,
+    0x8002002004100802,
+    0x30010002084c0007,
+    0x0888221800813004,
+    0x4000002840840112,
+];
+
+const BISHOP_MAGICS: [u64; 64] = [
+    0xa010041108003100,
+    0x006082020a002900,
+    0x6810010619200000,
+    0x08281a0520000408,
+    0x0001104001000400,
+    0x0018901008048400,
+    0x00040a0210245280,
+    0x000200210808a402,
+    0x9140048410821200,
+    0x0800091010820041,
+    0x20504804832202c0,
+    0x0100091401081000,
+    0x8021011140000012,
+    0x0810020804450400,
+    0x208b0542109008a2,
+    0x0080084a08040204,
+    0x0040e2a80811244c,
+    0x2505022008008108,
+    0x0430220100420040,
+    0x010a040420220040,
+    0x1105000290400000,
+    0x0093001200822120,
+    0x4000a62048043004,
+    0x280120048a015004,
+    0x006090002a020814,
+    0x44042000240800d0,
+    0x01102800040a4400,
+    0x1004080080220040,
+    0x0001001011004024,
+    0x0010044000805040,
+    0x0914041200820100,
+    0x0004821012821480,
+    0x0024040500c05021,
+    0x0088611002080200,
+    0x0116080a00040020,
+    0x4000020080080080,
+    0x2450450140840040,
+    0x0000880201484100,
+    0x0222020404020092,
+    0x8081110600002e00,
+    0x2842101105000801,
+    0x1100809008001025,
+    0x00020202221c0400,
+    0x0422014022009020,
+    0x0210046102100c00,
+    0xc004008082029102,
+    0x00aa461801101200,
+    0x0404080080201108,
+    0x020542108c205002,
+    0x0410544804100100,
+    0x0040910841100000,
+    0x0400200042021100,
+    0x00004204850400c0,
+    0x0200100410a42102,
+    0x1040020801210102,
+    0x0805040410420000,
+    0x2884804130100200,
+    0x800c262201242000,
+    0x1058000194108800,
+    0x0014221054420204,
+    0x0104000012a02200,
+    0x0200881003300100,
+    0x0140400202840100,
+    0x0402020801010201,
+];
+
+// attacks of every piece from every square, computed once
+pub struct Attacks {
+    knight: [Bitboard; 64],
+    king: [Bitboard; 64],
+    // squares attacked by a pawn of each color, white first
+    pawn: [[Bitboard; 64]; 2],
+    rook_magics: Vec<Magic>,
+    bishop_magics: Vec<Magic>,
+    sliding: Vec<Bitboard>,
+}
+
+static ATTACKS: OnceLock<Attacks> = OnceLock::new();
+
+impl Attacks {
+    pub fn get() -> &'static Attacks {
+        ATTACKS.get_or_init(Attacks::new)
+    }
+
+    pub fn knight(&self, square: usize) -> Bitboard {
+        self.knight[square]
+    }
+
+    pub fn king(&self, square: usize) -> Bitboard {
+        self.king[square]
+    }
+
+    pub fn pawn(&self, color: PColor, square: usize) -> Bitboard {
+        self.pawn[color as usize][square]
+    }
+
+    pub fn rook(&self, square: usize, occupied: Bitboard) -> Bitboard {
+        self.sliding[self.rook_magics[square].index(occupied)]
+    }
+
+    pub fn bishop(&self, square: usize, occupied: Bitboard) -> Bitboard {
+        self.sliding[self.bishop_magics[square].index(occupied)]
+    }
+
+    pub fn queen(&self, square: usize, occupied: Bitboard) -> Bitboard {
+        self.rook(square, occupied) | self.bishop(square, occupied)
+    }
+
+    // -------------------------------------------
+    // ------------ PRIVATE FUNCTIONS ------------
+    // -------------------------------------------
+
+    fn new() -> Attacks {
+        let precomputed = precomputed_move_data();
+        let mut attacks = Attacks {
+            knight: [0; 64],
+            king: [0; 64],
+            pawn: [[0; 64]; 2],
+            rook_magics: Vec::new(),
+            bishop_magics: Vec::new(),
+            sliding: Vec::new(),
+        };
+
+        for (square, ranges) in precomputed.iter().enumerate() {
+            for (index, offset) in DIRECTION_OFFSET.iter().enumerate() {
+                if ranges[index] == 0 {
+                    continue;
+                }
+                let target = (square as i8 + offset) as usize;
+                attacks.king[square] |= bit(target);
+                // pawns take diagonally towards the enemy side
+                match index {
+                    4 | 6 => attacks.pawn[PColor::White as usize][square] |= bit(target),
+                    5 | 7 => attacks.pawn[PColor::Black as usize][square] |= bit(target),
+                    _ => {}
+                }
+            }
+            attacks.knight[square] = knight_attacks(square);
+        }
+
+        for (square, magic) in ROOK_MAGICS.iter().enumerate() {
+            let magic = attacks.fill_magic(square, *magic, &precomputed, 0..4);
+            attacks.rook_magics.push(magic);
+        }
+        for (square, magic) in BISHOP_MAGICS.iter().enumerate() {
+            let magic = attacks.fill_magic(square, *magic, &precomputed, 4..8);
+            attacks.bishop_magics.push(magic);
+        }
+        attacks
+    }
+
+    // appends the attacks of the square for every blocker set, at the slot
+    // the magic number gives
+    fn fill_magic(
+        &mut self,
+        square: usize,
+        magic: u64,
+        precomputed: &[[i8; 8]; 64],
+        directions: std::ops::Range<usize>,
+    ) -> Magic {
+        let mask = relevant_blockers(square, precomputed, directions.clone());
+        let magic = Magic {
+            mask,
+            magic,
+            shift: 64 - mask.count_ones(),
+            offset: self.sliding.len(),
+        };
+        self.sliding
+            .resize(magic.offset + (1 << mask.count_ones()), 0);
+
+        // every subset of the mask, enumerated with the carry-rippler trick
+        let mut occupied: Bitboard = 0;
+        loop {
+            let attacks = ray_attacks(square, occupied, precomputed, directions.clone());
+            let slot = &mut self.sliding[magic.index(occupied)];
+            debug_assert!(*slot == 0 || *slot == attacks, "bad magic for {}", square);
+            *slot = attacks;
+            occupied = occupied.wrapping_sub(mask) & mask;
+            if occupied == 0 {
+                break;
+            }
+        }
+        magic
+    }
+}
+
+// squares reached sliding in the given directions until the first blocker,
+// which is included
+fn ray_attacks(
+    square: usize,
+    occupied: Bitboard,
+    precomputed: &[[i8; 8]; 64],
+    directions: std::ops::Range<usize>,
+) -> Bitboard {
+    let mut attacks: Bitboard = 0;
+    for index in directions {
+        for n in 0..precomputed[square][index] {
+            let target = (square as i8 + DIRECTION_OFFSET[index] * (n + 1)) as usize;
+            attacks |= bit(target);
+            if occupied & bit(target) != 0 {
+                break;
+            }
+        }
+    }
+    attacks
+}
+
+// squares whose content changes the attacks of a slider, the last square
+// of each ray is attacked whether it is occupied or not
+fn relevant_blockers(
+    square: usize,
+    precomputed: &[[i8; 8]; 64],
+    directions: std::ops::Range<usize>,
+) -> Bitboard {
+    let mut mask: Bitboard = 0;
+    for index in directions {
+        for n in 0..precomputed[square][index] - 1 {
+            mask |= bit((square as i8 + DIRECTION_OFFSET[index] * (n + 1)) as usize);
+        }
+    }
+    mask
+}
+
+fn knight_attacks(square: usize) -> Bitboard {
+    let (y, x) = ((square / 8) as isize, (square % 8) as isize);
+    let mut attacks: Bitboard = 0;
+    for (dy, dx) in [
+        (-2, -1),
+        (-2, 1),
+        (-1, -2),
+        (-1, 2),
+        (1, -2),
+        (1, 2),
+        (2, -1),
+        (2, 1),
+    ] {
+        let (ty, tx) = (y + dy, x + dx);
+        if (0..8).contains(&ty) && (0..8).contains(&tx) {
+            attacks |= bit((ty * 8 + tx) as usize);
+        }
+    }
+    attacks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::square;
+
+    fn set(names: &[&str]) -> Bitboard {
+        names
+            .iter()
+            .fold(0, |set, name| set | bit(square::parse(name).unwrap()))
+    }
+
+    #[test]
+    fn sliding_attacks_stop_on_blockers() {
+        let attacks = Attacks::get();
+        let d4 = square::parse("d4").unwrap();
+        let occupied = set(&["d6", "b4", "f6", "c3", "h4"]);
+
+        assert_eq!(
+            attacks.rook(d4, occupied),
+            set(&["d5", "d6", "d3", "d2", "d1", "c4", "b4", "e4", "f4", "g4", "h4"])
+        );
+        assert_eq!(
+            attacks.bishop(d4, occupied),
+            set(&["e5", "f6", "c3", "c5", "b6", "a7", "e3", "f2", "g1"])
+        );
+    }
+
+    #[test]
+    fn every_blocker_set_is_looked_up_correctly() {
+        let attacks = Attacks::get();
+        let precomputed = precomputed_move_data();
+        // xorshift, to get a few arbitrary blocker sets per square
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        for square in 0..64 {
+            for _ in 0..100 {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                let occupied = seed & seed.rotate_left(17);
+                assert_eq!(
+                    attacks.rook(square, occupied),
+                    ray_attacks(square, occupied, &precomputed, 0..4)
+                );
+                assert_eq!(
+                    attacks.bishop(square, occupied),
+                    ray_attacks(square, occupied, &precomputed, 4..8)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn leaper_attacks() {
+        let attacks = Attacks::get();
+        let square = |name| square::parse(name).unwrap();
+
+        assert_eq!(attacks.knight(square("a8")), set(&["b6", "c7"]));
+        assert_eq!(attacks.king(square("h1")), set(&["g1", "g2", "h2"]));
+        assert_eq!(
+            attacks.pawn(PColor::White, square("e4")),
+            set(&["d5", "f5"])
+        );
+        assert_eq!(attacks.pawn(PColor::Black, square("a5")), set(&["b4"]));
+    }
+}
@@ -1,25 +1,25 @@
-use super::piece::PColor;
-use super::piece::Piece;
-use super::piece::PieceTextures;
+use super::bitboard::{self, Bitboard};
+use super::piece::{PColor, Piece, PieceType};
 
-use crate::common::canvas_display;
-use crate::common::misc;
-
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::{TextureCreator, WindowCanvas};
-use sdl2::video::WindowContext;
-
-pub struct Board<'a> {
+#[derive(Clone)]
+pub struct Board {
     pub size: usize,
-    pub board: Vec<Option<Piece>>,
-    pub white: Color,
-    pub black: Color,
-    pub piece_textures: PieceTextures<'a>,
+    // piece on each square, if any, to look a square up directly
+    board: Vec<Option<Piece>>,
+    // the same placement as sets of squares, one per piece type and one
+    // per color, kept up to date by `set` and `set_square`
+    pieces: [Bitboard; 6],
+    colors: [Bitboard; 2],
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Board::new()
+    }
 }
 
-impl Board<'_> {
-    pub fn new(renderer: &TextureCreator<WindowContext>) -> Board<'_> {
+impl Board {
+    pub fn new() -> Board {
         let mut board: Vec<Option<Piece>> = Vec::new();
 
         // initializing board
@@ -30,9 +30,8 @@ impl Board<'_> {
         Board {
             size: 8,
             board,
-            white: Color::RGBA(234, 203, 164, 255),
-            black: Color::RGBA(185, 112, 68, 255),
-            piece_textures: Piece::create_piece_textures(renderer),
+            pieces: [0; 6],
+            colors: [0; 2],
         }
     }
 
@@ -54,115 +53,115 @@ impl Board<'_> {
         if y >= self.size || x >= self.size {
             return;
         }
-        self.board[y * self.size + x] = value;
+        self.set_square(y * self.size + x, value);
     }
 
     pub fn set_square(&mut self, square: usize, value: Option<Piece>) {
         if square >= self.size * self.size {
             return;
         }
+        if let Some(p) = self.board[square] {
+            self.pieces[p.r#type as usize] &= !bitboard::bit(square);
+            self.colors[p.color as usize] &= !bitboard::bit(square);
+        }
+        if let Some(p) = value {
+            self.pieces[p.r#type as usize] |= bitboard::bit(square);
+            self.colors[p.color as usize] |= bitboard::bit(square);
+        }
         self.board[square] = value;
     }
 
-    pub fn init(&mut self) {
-        let fen = String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR");
-        self.fen_init(fen); //String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR"));
+    // content of every square, from a8 to h1
+    pub fn squares(&self) -> &[Option<Piece>] {
+        &self.board
+    }
+
+    pub fn pieces(&self, r#type: PieceType, color: PColor) -> Bitboard {
+        self.pieces[r#type as usize] & self.colors[color as usize]
+    }
+
+    pub fn color(&self, color: PColor) -> Bitboard {
+        self.colors[color as usize]
+    }
+
+    pub fn occupied(&self) -> Bitboard {
+        self.colors[0] | self.colors[1]
+    }
+
+    pub fn king_square(&self, color: PColor) -> Option<usize> {
+        bitboard::squares(self.pieces(PieceType::King, color)).next()
     }
 
-    pub fn draw_board(&self, canvas: &mut WindowCanvas, width: i32, height: i32) {
-        let mut alternate: u8 = 0;
-
-        let case_height: i32 = height / self.size as i32;
-        let case_width: i32 = width / self.size as i32;
-
-        for y in 0..self.size {
-            for x in 0..self.size {
-                if x != 0 {
-                    alternate = 1 - alternate;
-                }
-
-                match alternate {
-                    0 => canvas.set_draw_color(self.white),
-                    1 => canvas.set_draw_color(self.black),
-                    _ => {}
-                }
-                canvas_display::canvas_fill(
-                    canvas,
-                    Rect::new(
-                        x as i32 * case_width,
-                        y as i32 * case_height,
-                        case_width as u32,
-                        case_height as u32,
-                    ),
-                );
-            }
+    // neither player can ever checkmate, whatever moves are played: only
+    // kings are left, with at most a single minor piece or with bishops that
+    // all stand on squares of the same color
+    pub fn has_insufficient_material(&self) -> bool {
+        let heavy = [PieceType::Pawn, PieceType::Rook, PieceType::Queen]
+            .iter()
+            .any(|r#type| self.pieces[*r#type as usize] != 0);
+        if heavy {
+            return false;
         }
+        let knights = self.pieces[PieceType::Knight as usize];
+        let bishops = self.pieces[PieceType::Bishop as usize];
+        if bishops == 0 {
+            return knights.count_ones() <= 1;
+        }
+        knights == 0
+            && (bishops & bitboard::LIGHT_SQUARES == 0 || bishops & !bitboard::LIGHT_SQUARES == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::position::Position;
+
+    fn insufficient(notation: &str) -> bool {
+        Position::from_fen(notation)
+            .unwrap()
+            .board
+            .has_insufficient_material()
     }
 
-    pub fn draw_pieces(&self, canvas: &mut WindowCanvas, width: i32, height: i32) {
-        let case_height: i32 = height / self.size as i32;
-        let case_width: i32 = width / self.size as i32;
-
-        for y in 0..self.size {
-            for x in 0..self.size {
-                if let Some(p) = self.get(y, x) {
-                    let rect = Rect::new(
-                        x as i32 * case_width,
-                        y as i32 * case_height,
-                        case_width as u32,
-                        case_height as u32,
-                    );
-                    match p.color {
-                        PColor::White => {
-                            canvas_display::canvas_copy(
-                                canvas,
-                                self.piece_textures.white_textures.get(&p.r#type).unwrap(),
-                                None,
-                                Some(rect),
-                            );
-                        }
-                        PColor::Black => {
-                            canvas_display::canvas_copy(
-                                canvas,
-                                self.piece_textures.black_textures.get(&p.r#type).unwrap(),
-                                None,
-                                Some(rect),
-                            );
-                        }
-                    }
-                }
-            }
+    #[test]
+    fn dead_positions() {
+        for notation in [
+            // king against king
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            // king and bishop against king
+            "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K1b1 b - - 0 1",
+            // king and knight against king
+            "4k3/8/8/8/8/8/8/1N2K3 w - - 0 1",
+            "1n2k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            // bishops against bishops, all on squares of the same color
+            "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/B1B1K3 w - - 0 1",
+            "2b1k3/8/8/8/8/8/8/3BKB2 b - - 0 1",
+        ] {
+            assert!(insufficient(notation), "{}", notation);
         }
     }
 
-    // -------------------------------------------
-    // ------------ PRIVATE FUNCTIONS ------------
-    // -------------------------------------------
-
-    fn fen_init(&mut self, notation: String) {
-        let mut index: usize = 0;
-        for c in notation.chars() {
-            if c == '/' {
-                println!("Skipped char /");
-                continue;
-            }
-            let tmp = misc::to_digit(c);
-
-            if let Some(digit) = tmp {
-                println!("char {} is number: {}", c, digit);
-                index += digit as usize;
-            } else {
-                match Piece::new(c) {
-                    None => {
-                        println!("{} is not a valid symbol for a chess piece !", c)
-                    }
-                    p => {
-                        self.board[index] = p;
-                        println!("Generated new piece with symbol: {}", c);
-                    }
-                }
-                index += 1;
-            }
+    #[test]
+    fn mate_is_still_possible() {
+        for notation in [
+            // any pawn, rook or queen
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+            "3qk3/8/8/8/8/8/8/4K3 w - - 0 1",
+            // two knights can mate with the help of the other side
+            "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1",
+            // knight against knight or bishop
+            "1n2k3/8/8/8/8/8/8/1N2K3 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/1N2K3 w - - 0 1",
+            // bishop and knight
+            "4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1",
+            // bishops on squares of different colors
+            "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2BBK3 w - - 0 1",
+        ] {
+            assert!(!insufficient(notation), "{}", notation);
         }
     }
 }
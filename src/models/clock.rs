@@ -0,0 +1,137 @@
+use super::piece::PColor;
+
+use std::time::{Duration, Instant};
+
+pub struct Clock {
+    pub white: Duration,
+    pub black: Duration,
+    // time each player started with
+    pub base: Duration,
+    pub increment: Duration,
+    // player whose time is running, and since when
+    running: Option<(PColor, Instant)>,
+}
+
+impl Clock {
+    pub fn new(base: Duration, increment: Duration) -> Clock {
+        Clock {
+            white: base,
+            black: base,
+            base,
+            increment,
+            running: Some((PColor::White, Instant::now())),
+        }
+    }
+
+    // parses a time control written as "minutes+increment", e.g. "5+3"
+    pub fn from_control(control: &str) -> Option<Clock> {
+        let (minutes, increment) = match control.split_once('+') {
+            None => (control, "0"),
+            Some(parts) => parts,
+        };
+        let minutes: u64 = minutes.trim().parse().ok()?;
+        let increment: u64 = increment.trim().parse().ok()?;
+        Some(Clock::new(
+            Duration::from_secs(minutes * 60),
+            Duration::from_secs(increment),
+        ))
+    }
+
+    pub fn remaining(&self, color: PColor) -> Duration {
+        let stored = match color {
+            PColor::White => self.white,
+            PColor::Black => self.black,
+        };
+        match self.running {
+            Some((player, since)) if player == color => stored.saturating_sub(since.elapsed()),
+            _ => stored,
+        }
+    }
+
+    // ends the turn of the running player, who receives the increment
+    pub fn punch(&mut self) {
+        if let Some((player, _)) = self.running {
+            let left = self.remaining(player) + self.increment;
+            let next = match player {
+                PColor::White => {
+                    self.white = left;
+                    PColor::Black
+                }
+                PColor::Black => {
+                    self.black = left;
+                    PColor::White
+                }
+            };
+            self.running = Some((next, Instant::now()));
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if let Some((player, _)) = self.running {
+            let left = self.remaining(player);
+            match player {
+                PColor::White => self.white = left,
+                PColor::Black => self.black = left,
+            }
+            self.running = None;
+        }
+    }
+
+    // runs the time of the given player, without increment, e.g. when a
+    // move is taken back
+    pub fn hand_over(&mut self, color: PColor) {
+        self.stop();
+        self.start(color);
+    }
+
+    // runs the time of the given player, the time spent so far by the
+    // running one not being counted, e.g. when the game starts
+    pub fn start(&mut self, color: PColor) {
+        self.running = Some((color, Instant::now()));
+    }
+
+    pub fn flagged(&self) -> Option<PColor> {
+        match self.running {
+            Some((player, _)) if self.remaining(player).is_zero() => Some(player),
+            _ => None,
+        }
+    }
+
+    pub fn display(&self, color: PColor) -> String {
+        let seconds = self.remaining(color).as_secs();
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_time_controls() {
+        let clock = Clock::from_control("5+3").unwrap();
+        assert_eq!(clock.base, Duration::from_secs(300));
+        assert_eq!(clock.increment, Duration::from_secs(3));
+        assert_eq!(clock.black, Duration::from_secs(300));
+
+        let clock = Clock::from_control("10").unwrap();
+        assert_eq!(clock.base, Duration::from_secs(600));
+        assert_eq!(clock.increment, Duration::ZERO);
+
+        for control in ["", "x", "5+", "+3", "5+x", "-1+0", "5:00"] {
+            assert!(Clock::from_control(control).is_none(), "{}", control);
+        }
+    }
+
+    #[test]
+    fn the_running_player_flags() {
+        let mut clock = Clock::new(Duration::ZERO, Duration::from_secs(1));
+        assert_eq!(clock.flagged(), Some(PColor::White));
+        // the increment comes once the move is played
+        clock.punch();
+        assert_eq!(clock.remaining(PColor::White), Duration::from_secs(1));
+        assert_eq!(clock.flagged(), Some(PColor::Black));
+        clock.stop();
+        assert_eq!(clock.flagged(), None);
+    }
+}
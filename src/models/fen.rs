@@ -0,0 +1,333 @@
+use super::board::Board;
+use super::piece::{PColor, Piece, PieceType};
+use super::position::{CastlingRights, Position};
+use super::square;
+
+use crate::common::misc;
+
+use std::error::Error;
+use std::fmt;
+
+pub const START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum FenError {
+    // the notation must hold 6 fields (the two clocks may be omitted)
+    FieldCount(usize),
+    // the placement must describe exactly 8 ranks
+    RankCount(usize),
+    // the given rank (8 to 1) does not describe exactly 8 squares
+    RankLength(usize),
+    InvalidPiece(char),
+    // each player must have exactly one king
+    KingCount(PColor),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::FieldCount(count) => write!(f, "expected 6 fields, found {}", count),
+            FenError::RankCount(count) => write!(f, "expected 8 ranks, found {}", count),
+            FenError::RankLength(rank) => write!(f, "rank {} does not hold 8 squares", rank),
+            FenError::InvalidPiece(c) => write!(f, "{} is not a valid symbol for a chess piece", c),
+            FenError::KingCount(color) => write!(f, "{:?} must have exactly one king", color),
+            FenError::InvalidSideToMove(s) => write!(f, "invalid side to move '{}'", s),
+            FenError::InvalidCastling(s) => write!(f, "invalid castling rights '{}'", s),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{}'", s),
+            FenError::InvalidHalfmoveClock(s) => write!(f, "invalid halfmove clock '{}'", s),
+            FenError::InvalidFullmoveNumber(s) => write!(f, "invalid fullmove number '{}'", s),
+        }
+    }
+}
+
+impl Error for FenError {}
+
+pub fn parse(notation: &str) -> Result<Position, FenError> {
+    let fields: Vec<&str> = notation.split_whitespace().collect();
+    if fields.len() != 6 && fields.len() != 4 {
+        return Err(FenError::FieldCount(fields.len()));
+    }
+
+    let board = parse_placement(fields[0])?;
+    let current_player = match fields[1] {
+        "w" => PColor::White,
+        "b" => PColor::Black,
+        s => return Err(FenError::InvalidSideToMove(String::from(s))),
+    };
+    let castling = parse_castling(fields[2])?;
+    let en_passant = parse_en_passant(fields[3], current_player, &board)?;
+    let (halfmove_clock, fullmove_number) = if fields.len() == 6 {
+        let halfmove: u32 = fields[4]
+            .parse()
+            .map_err(|_| FenError::InvalidHalfmoveClock(String::from(fields[4])))?;
+        let fullmove: u32 = match fields[5].parse() {
+            Ok(n) if n > 0 => n,
+            _ => return Err(FenError::InvalidFullmoveNumber(String::from(fields[5]))),
+        };
+        (halfmove, fullmove)
+    } else {
+        (0, 1)
+    };
+
+    Ok(Position::from_states(
+        board,
+        current_player,
+        castling,
+        en_passant,
+        halfmove_clock,
+        fullmove_number,
+    ))
+}
+
+pub fn write(position: &Position) -> String {
+    let mut placement = String::new();
+    for y in 0..position.board.size {
+        let mut empty: u8 = 0;
+        for x in 0..position.board.size {
+            match position.board.get(y, x) {
+                None => empty += 1,
+                Some(p) => {
+                    if empty > 0 {
+                        placement.push((b'0' + empty) as char);
+                        empty = 0;
+                    }
+                    placement.push(p.symbol());
+                }
+            }
+        }
+        if empty > 0 {
+            placement.push((b'0' + empty) as char);
+        }
+        if y + 1 < position.board.size {
+            placement.push('/');
+        }
+    }
+
+    let side = match position.current_player {
+        PColor::White => "w",
+        PColor::Black => "b",
+    };
+
+    let rights = position.castling;
+    let mut castling = String::new();
+    for (allowed, symbol) in [
+        (rights.white_king_side, 'K'),
+        (rights.white_queen_side, 'Q'),
+        (rights.black_king_side, 'k'),
+        (rights.black_queen_side, 'q'),
+    ] {
+        if allowed {
+            castling.push(symbol);
+        }
+    }
+    if castling.is_empty() {
+        castling.push('-');
+    }
+
+    let en_passant = match position.en_passant {
+        None => String::from("-"),
+        Some(square) => square::name(square),
+    };
+
+    format!(
+        "{} {} {} {} {} {}",
+        placement, side, castling, en_passant, position.halfmove_clock, position.fullmove_number
+    )
+}
+
+// -------------------------------------------
+// ------------ PRIVATE FUNCTIONS ------------
+// -------------------------------------------
+
+fn parse_placement(placement: &str) -> Result<Board, FenError> {
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::RankCount(ranks.len()));
+    }
+
+    let mut board = Board::new();
+    let mut kings: [u8; 2] = [0, 0];
+    for (y, rank) in ranks.iter().enumerate() {
+        let mut x: usize = 0;
+        for c in rank.chars() {
+            if let Some(digit) = misc::to_digit(c) {
+                if digit == 0 || digit > 8 {
+                    return Err(FenError::InvalidPiece(c));
+                }
+                x += digit as usize;
+                continue;
+            }
+            let piece = match Piece::new(c) {
+                None => return Err(FenError::InvalidPiece(c)),
+                Some(p) => p,
+            };
+            if x >= 8 {
+                return Err(FenError::RankLength(8 - y));
+            }
+            if piece.is_type(PieceType::King) {
+                kings[piece.color as usize] += 1;
+            }
+            board.set(y, x, Some(piece));
+            x += 1;
+        }
+        if x != 8 {
+            return Err(FenError::RankLength(8 - y));
+        }
+    }
+
+    for color in [PColor::White, PColor::Black] {
+        if kings[color as usize] != 1 {
+            return Err(FenError::KingCount(color));
+        }
+    }
+    Ok(board)
+}
+
+fn parse_castling(field: &str) -> Result<CastlingRights, FenError> {
+    let mut rights = CastlingRights::none();
+    if field == "-" {
+        return Ok(rights);
+    }
+    for c in field.chars() {
+        let right = match c {
+            'K' => &mut rights.white_king_side,
+            'Q' => &mut rights.white_queen_side,
+            'k' => &mut rights.black_king_side,
+            'q' => &mut rights.black_queen_side,
+            _ => return Err(FenError::InvalidCastling(String::from(field))),
+        };
+        if *right {
+            // the same right given twice
+            return Err(FenError::InvalidCastling(String::from(field)));
+        }
+        *right = true;
+    }
+    Ok(rights)
+}
+
+fn parse_en_passant(
+    field: &str,
+    current_player: PColor,
+    board: &Board,
+) -> Result<Option<usize>, FenError> {
+    if field == "-" {
+        return Ok(None);
+    }
+    // the skipped square is behind a pawn of the player that just moved
+    let (expected_row, pushed): (usize, i8) = match current_player {
+        PColor::White => (2, 8),
+        PColor::Black => (5, -8),
+    };
+    let pushed_pawn = |square: usize| {
+        board
+            .get_square((square as i8 + pushed) as usize)
+            .is_some_and(|piece| {
+                piece.is_type(PieceType::Pawn) && piece.is_color(current_player.opposite())
+            })
+    };
+    match square::parse(field) {
+        Some(square)
+            if square / 8 == expected_row
+                && board.get_square(square).is_none()
+                && pushed_pawn(square) =>
+        {
+            Ok(Some(square))
+        }
+        _ => Err(FenError::InvalidEnPassant(String::from(field))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_exactly() {
+        for notation in [
+            START_POSITION,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "4k3/8/8/8/8/8/8/4K2R b K - 37 112",
+        ] {
+            assert_eq!(parse(notation).unwrap().to_fen(), notation);
+        }
+    }
+
+    #[test]
+    fn clocks_may_be_omitted() {
+        let position = parse("4k3/8/8/8/8/8/8/4K3 b - -").unwrap();
+        assert_eq!(position.to_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+    }
+
+    #[test]
+    fn reads_every_field() {
+        let position = parse("r3k3/8/8/3pP3/8/8/8/4K2R w Kq d6 3 20").unwrap();
+        assert!(position.current_player == PColor::White);
+        assert!(position.castling.white_king_side && !position.castling.white_queen_side);
+        assert!(position.castling.black_queen_side && !position.castling.black_king_side);
+        assert_eq!(position.en_passant, square::parse("d6"));
+        assert_eq!(position.halfmove_clock, 3);
+        assert_eq!(position.fullmove_number, 20);
+    }
+
+    #[test]
+    fn rejects_malformed_notation() {
+        let cases = [
+            ("8/8/8/8/8/8/8/8 w - - 0", FenError::FieldCount(5)),
+            ("4k3/8/8/8/8/8/4K3 w - - 0 1", FenError::RankCount(7)),
+            ("4k3/8/8/8/8/8/8/4K4 w - - 0 1", FenError::RankLength(1)),
+            (
+                "4k3/8/8/8/8/8/8/4Kx2 w - - 0 1",
+                FenError::InvalidPiece('x'),
+            ),
+            (
+                "8/8/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::KingCount(PColor::Black),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+                FenError::InvalidSideToMove(String::from("x")),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w KK - 0 1",
+                FenError::InvalidCastling(String::from("KK")),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e3 0 1",
+                FenError::InvalidEnPassant(String::from("e3")),
+            ),
+            // no pawn was pushed past the square
+            (
+                "4k3/8/8/8/8/8/8/4K3 b - e3 0 1",
+                FenError::InvalidEnPassant(String::from("e3")),
+            ),
+            (
+                "4k3/8/8/3PP3/8/8/8/4K3 w - d6 0 1",
+                FenError::InvalidEnPassant(String::from("d6")),
+            ),
+            (
+                "4k3/8/3n4/3pP3/8/8/8/4K3 w - d6 0 1",
+                FenError::InvalidEnPassant(String::from("d6")),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - -1 1",
+                FenError::InvalidHalfmoveClock(String::from("-1")),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 0",
+                FenError::InvalidFullmoveNumber(String::from("0")),
+            ),
+        ];
+        for (notation, error) in cases {
+            assert_eq!(parse(notation).err(), Some(error));
+        }
+    }
+}
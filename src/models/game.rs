@@ -1,267 +1,507 @@
-use crate::common::canvas_display;
-
-use super::board::Board;
-use super::piece::PColor;
-use super::piece::Piece;
-use super::r#move::{Move, MoveAction, MoveGenerator};
-use super::sound::Sound;
-
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::{TextureCreator, WindowCanvas};
-use sdl2::video::WindowContext;
+use super::clock::Clock;
+use super::piece::{PColor, PieceType};
+use super::position::Position;
+use super::r#move::{Move, MoveGenerator};
+use super::status::{DrawReason, GameStatus};
 
 use std::collections::HashMap;
+use std::time::Duration;
+
+// a move of the game, with what is needed to write it down
+#[derive(Clone)]
+pub struct PlayedMove {
+    pub mv: Move,
+    pub san: String,
+    // time left to the player after the move, when the game has a clock
+    pub clock: Option<Duration>,
+}
 
-pub struct Game<'a> {
-    //board structure: used for piece placement and display
-    pub board: Board<'a>,
-    //Color of the player that is currently playing,
-    //Used for recognition of which pieces can be played
-    pub current_player: PColor,
-    // piece states, used in order to know which piece is currently being
-    // hold by the player
-    pub piece_hold: Option<Piece>,
-    pub x: usize,
-    pub y: usize,
-    // visual fx: used in order to display the last move on the board
+pub struct Game {
+    //position structure: piece placement, color of the player that is
+    //currently playing, castling, en passant and clocks
+    pub position: Position,
+    // position the game started from, and every move played since
+    pub start: Position,
+    pub moves: Vec<PlayedMove>,
+    // last move played, if any
     pub last_move: Option<Move>,
+    // game states: whether the game is still being played, how it ended
+    // otherwise, and the optional time control of both players
+    pub status: GameStatus,
+    pub clock: Option<Clock>,
+    // moves taken back, the last one being the next to play again
+    undone: Vec<PlayedMove>,
     // algorithmic states: used to generate moves for the pieces according
     // to chess rules
     possible_moves: HashMap<usize, Vec<Move>>,
     move_generator: MoveGenerator,
 }
 
-impl Game<'_> {
-    pub fn new(renderer: &TextureCreator<WindowContext>) -> Game<'_> {
-        let mut board = Board::new(renderer);
-        board.init();
-        let player = PColor::White;
+impl Game {
+    pub fn new(mut position: Position, clock: Option<Clock>) -> Game {
         let generator = MoveGenerator::new();
-        let possible_moves = generator.generate_moves(&mut board, player);
-
-        Game {
-            board,
-            current_player: player,
-            piece_hold: None,
-            x: 0,
-            y: 0,
+        let possible_moves = generator.generate_moves(&mut position);
+
+        let mut game = Game {
+            start: position.clone(),
+            moves: Vec::new(),
+            position,
             last_move: None,
+            status: GameStatus::Ongoing,
+            clock: None,
+            undone: Vec::new(),
             possible_moves,
             move_generator: generator,
-        }
+        };
+        game.set_clock(clock);
+        // the given position may already be over
+        game.update_status();
+        game
     }
 
-    pub fn select_piece(&mut self, x: i32, y: i32, width: u32, height: u32) {
-        let i: usize = self.board.size * y as usize / height as usize;
-        let j: usize = self.board.size * x as usize / width as usize;
-
-        let selected: Option<Piece> = self.board.get(i, j);
-        println!("found coordinate: ({}, {})", j, i);
-        match selected {
-            None => {}
-            Some(p) => {
-                if p.color == self.current_player {
-                    self.piece_hold = selected;
-                    self.x = j;
-                    self.y = i;
-                    println!("x: {}, y: {}", self.x, self.y);
-                    self.board.set(i, j, None);
-                }
+    // puts the game under a time control, the time of the player to move
+    // running from now on unless the game is over
+    pub fn set_clock(&mut self, clock: Option<Clock>) {
+        self.clock = clock;
+        if let Some(clock) = &mut self.clock {
+            if self.status.is_over() {
+                clock.stop();
+            } else {
+                clock.start(self.position.current_player);
             }
         }
     }
 
-    pub fn make_move(&mut self, x: i32, y: i32, width: u32, height: u32, sound: &Sound) {
-        if self.piece_hold.is_none() {
-            return;
+    // legal moves of the piece standing on the square
+    pub fn possible_moves(&self, square: usize) -> &[Move] {
+        match self.possible_moves.get(&square) {
+            None => &[],
+            Some(moves) => moves,
         }
-        let i: usize = self.board.size * y as usize / height as usize;
-        let j: usize = self.board.size * x as usize / width as usize;
-
-        let start: usize = self.y * self.board.size + self.x;
-        let end: usize = i * self.board.size + j;
-
-        println!("start given: {}, end given: {}", start, end);
-        let move_made = Move::is_valid(
-            start,
-            end,
-            &mut self.board,
-            &mut self.piece_hold.unwrap(),
-            &self.possible_moves,
-        );
+    }
 
-        match move_made {
-            MoveAction::Move => {
-                sound.play("move");
-            }
-            MoveAction::Take => {
-                sound.play("take");
-            }
-            MoveAction::Castle => {
-                sound.play("castle");
-            }
-            MoveAction::Incorrect => {
-                self.board.set(self.y, self.x, self.piece_hold);
-                self.reset_hold_piece_states();
+    pub fn find_move(
+        &self,
+        start: usize,
+        end: usize,
+        promotion: Option<PieceType>,
+    ) -> Option<Move> {
+        Move::find(start, end, promotion, &self.possible_moves)
+    }
+
+    // whether going from start to end needs a piece to be picked
+    pub fn is_promotion(&self, start: usize, end: usize) -> bool {
+        self.possible_moves(start)
+            .iter()
+            .any(|mv| mv.end() == end && mv.promotion().is_some())
+    }
+
+    // plays a legal move, returns false if the move is refused
+    pub fn play_move(&mut self, mv: Move) -> bool {
+        if self.status.is_over() || !self.possible_moves(mv.start()).contains(&mv) {
+            return false;
+        }
+        // a new move replaces the moves taken back
+        self.undone.clear();
+        self.record_move(mv);
+        true
+    }
+
+    // takes back the last move, the game going on even if it was over
+    pub fn undo(&mut self) -> Option<Move> {
+        let played = self.moves.pop()?;
+        self.position.unmake_move();
+        self.last_move = self.moves.last().map(|played| played.mv);
+        if let Some(clock) = &mut self.clock {
+            clock.hand_over(self.position.current_player);
+        }
+        self.update_new_moves();
+        self.status = GameStatus::Ongoing;
+        self.update_status();
+        let mv = played.mv;
+        self.undone.push(played);
+        Some(mv)
+    }
+
+    // plays again the last move taken back
+    pub fn redo(&mut self) -> Option<Move> {
+        if self.status.is_over() {
+            return None;
+        }
+        let played = self.undone.pop()?;
+        self.record_move(played.mv);
+        Some(played.mv)
+    }
+
+    // position after the first `ply` moves of the game
+    pub fn position_at(&self, ply: usize) -> Position {
+        let mut position = self.start.clone();
+        for played in self.moves.iter().take(ply) {
+            position.make_move(played.mv);
+        }
+        position
+    }
+
+    pub fn is_in_check(&self) -> bool {
+        self.move_generator
+            .is_in_check(&self.position.board, self.position.current_player)
+    }
+
+    // returns whether the game ended with the resignation
+    pub fn resign(&mut self) -> bool {
+        if self.status.is_over() {
+            return false;
+        }
+        self.end_game(GameStatus::Resignation(
+            self.position.current_player.opposite(),
+        ));
+        true
+    }
+
+    // flags the player to move once their time is over, to be called
+    // regularly, returns whether the game ended on time
+    pub fn update_clock(&mut self) -> bool {
+        if self.status.is_over() {
+            return false;
+        }
+        let flagged = match &self.clock {
+            None => return false,
+            Some(clock) => clock.flagged(),
+        };
+        match flagged {
+            None => false,
+            Some(color) => {
+                self.end_game(GameStatus::Timeout(color.opposite()));
+                true
             }
         }
-        if move_made != MoveAction::Incorrect {
-            self.update_after_move(start, end);
+    }
+
+    // draw the player to move may claim, by threefold repetition or the
+    // fifty-move rule
+    pub fn claimable_draw(&self) -> Option<DrawReason> {
+        if self.status.is_over() {
+            None
+        } else if self.position.repetitions() >= 3 {
+            Some(DrawReason::ThreefoldRepetition)
+        } else if self.position.halfmove_clock >= 100 {
+            Some(DrawReason::FiftyMoveRule)
+        } else {
+            None
         }
     }
 
-    pub fn draw(
-        &self,
-        canvas: &mut WindowCanvas,
-        width: i32,
-        height: i32,
-        mouse_x: i32,
-        mouse_y: i32,
-    ) {
-        self.board.draw_board(canvas, width, height);
-        self.draw_last_move(canvas, width, height);
-        self.draw_possible_moves(canvas, width, height);
-        self.board.draw_pieces(canvas, width, height);
-        self.draw_hold(canvas, width, height, mouse_x, mouse_y);
+    // returns whether the game ended with the claim
+    pub fn claim_draw(&mut self) -> bool {
+        match self.claimable_draw() {
+            None => false,
+            Some(reason) => {
+                self.end_game(GameStatus::Draw(reason));
+                true
+            }
+        }
+    }
+
+    pub fn title(&self) -> String {
+        let mut title = String::from("DRW Chess");
+        if let Some(clock) = &self.clock {
+            title.push_str(&format!(
+                " - White {} | Black {}",
+                clock.display(PColor::White),
+                clock.display(PColor::Black)
+            ));
+        }
+        if self.status.is_over() {
+            title.push_str(&format!(" - {}", self.status));
+        } else if let Some(reason) = self.claimable_draw() {
+            title.push_str(&format!(" - Draw by {} can be claimed", reason));
+        }
+        title
     }
 
     // -------------------------------------------
     // ------------ PRIVATE FUNCTIONS ------------
     // -------------------------------------------
 
+    fn record_move(&mut self, mv: Move) {
+        let san = self.position.to_san(mv);
+        let player = self.position.current_player;
+        self.position.make_move(mv);
+        self.update_after_move(mv);
+        self.moves.push(PlayedMove {
+            mv,
+            san,
+            clock: self.clock.as_ref().map(|clock| clock.remaining(player)),
+        });
+    }
+
     fn switch_player(&mut self) {
-        self.current_player = if self.current_player == PColor::White {
-            PColor::Black
-        } else {
-            PColor::White
-        };
+        if let Some(clock) = &mut self.clock {
+            clock.punch();
+        }
     }
 
-    fn draw_hold(
-        &self,
-        canvas: &mut WindowCanvas,
-        width: i32,
-        height: i32,
-        mouse_x: i32,
-        mouse_y: i32,
-    ) {
-        match self.piece_hold {
-            None => {}
-            Some(p) => {
-                let case_height: i32 = height / self.board.size as i32;
-                let case_width: i32 = width / self.board.size as i32;
-
-                let rect = Rect::new(
-                    mouse_x - case_width / 2,
-                    mouse_y - case_height / 2,
-                    case_width as u32,
-                    case_height as u32,
-                );
-
-                match p.color {
-                    PColor::White => {
-                        canvas_display::canvas_copy(
-                            canvas,
-                            self.board
-                                .piece_textures
-                                .white_textures
-                                .get(&p.r#type)
-                                .unwrap(),
-                            None,
-                            Some(rect),
-                        );
-                    }
-                    PColor::Black => {
-                        canvas_display::canvas_copy(
-                            canvas,
-                            self.board
-                                .piece_textures
-                                .black_textures
-                                .get(&p.r#type)
-                                .unwrap(),
-                            None,
-                            Some(rect),
-                        );
-                    }
-                }
-            }
+    fn end_game(&mut self, status: GameStatus) {
+        self.status = status;
+        if let Some(clock) = &mut self.clock {
+            clock.stop();
         }
     }
 
-    fn draw_last_move(&self, canvas: &mut WindowCanvas, width: i32, height: i32) {
-        match self.last_move {
-            None => {}
-            Some(m) => {
-                let case_height: i32 = height / self.board.size as i32;
-                let case_width: i32 = width / self.board.size as i32;
-
-                canvas.set_draw_color(Color::RGBA(0, 255, 0, 30));
-                canvas_display::canvas_fill(
-                    canvas,
-                    Rect::new(
-                        (m.start % self.board.size) as i32 * case_width,
-                        (m.start / self.board.size) as i32 * case_height,
-                        case_width as u32,
-                        case_height as u32,
-                    ),
-                );
-                canvas.set_draw_color(Color::RGBA(255, 255, 0, 30));
-                canvas_display::canvas_fill(
-                    canvas,
-                    Rect::new(
-                        (m.end % self.board.size) as i32 * case_width,
-                        (m.end / self.board.size) as i32 * case_height,
-                        case_width as u32,
-                        case_height as u32,
-                    ),
-                );
+    fn update_after_move(&mut self, mv: Move) {
+        self.switch_player();
+        self.update_last_move(mv);
+        self.update_new_moves();
+        self.update_status();
+    }
+
+    fn update_status(&mut self) {
+        let has_moves = self.possible_moves.values().any(|moves| !moves.is_empty());
+        let status = if !has_moves {
+            if self.is_in_check() {
+                GameStatus::Checkmate(self.position.current_player.opposite())
+            } else {
+                GameStatus::Stalemate
             }
+        } else if self.position.board.has_insufficient_material() {
+            GameStatus::Draw(DrawReason::InsufficientMaterial)
+        } else if self.position.repetitions() >= 5 {
+            GameStatus::Draw(DrawReason::FivefoldRepetition)
+        } else if self.position.halfmove_clock >= 150 {
+            GameStatus::Draw(DrawReason::SeventyFiveMoveRule)
+        } else {
+            GameStatus::Ongoing
+        };
+        if status.is_over() {
+            self.end_game(status);
+        } else {
+            self.status = status;
         }
     }
 
-    fn draw_possible_moves(&self, canvas: &mut WindowCanvas, width: i32, height: i32) {
-        if self.piece_hold.is_none() {
-            return;
+    fn update_new_moves(&mut self) {
+        self.possible_moves = self.move_generator.generate_moves(&mut self.position);
+    }
+
+    fn update_last_move(&mut self, mv: Move) {
+        self.last_move = Some(mv);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::r#move::MoveKind;
+    use crate::models::square;
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for name in moves {
+            let start = square::parse(&name[..2]).unwrap();
+            let end = square::parse(&name[2..]).unwrap();
+            let mv = game.find_move(start, end, None).unwrap();
+            assert!(game.play_move(mv));
         }
-        let square: usize = self.y * self.board.size + self.x;
-        let case_height: i32 = height / self.board.size as i32;
-        let case_width: i32 = width / self.board.size as i32;
-
-        canvas.set_draw_color(Color::RGBA(255, 0, 0, 200));
-        for mv in &self.possible_moves[&square] {
-            canvas_display::canvas_fill(
-                canvas,
-                Rect::new(
-                    (mv.end % self.board.size) as i32 * case_width,
-                    (mv.end / self.board.size) as i32 * case_height,
-                    case_width as u32,
-                    case_height as u32,
-                ),
-            );
+    }
+
+    #[test]
+    fn fools_mate_is_checkmate() {
+        let mut game = Game::new(Position::new(), None);
+        play(&mut game, &["f2f3", "e7e5", "g2g4"]);
+        assert!(game.status == GameStatus::Ongoing);
+        play(&mut game, &["d8h4"]);
+        assert!(game.status == GameStatus::Checkmate(PColor::Black));
+        // white is left without a legal move
+        let mv = game
+            .possible_moves(square::parse("a2").unwrap())
+            .first()
+            .copied();
+        assert_eq!(mv, None);
+    }
+
+    #[test]
+    fn no_move_without_check_is_stalemate() {
+        let game = Game::new(
+            Position::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap(),
+            None,
+        );
+        assert!(game.status == GameStatus::Stalemate);
+    }
+
+    #[test]
+    fn the_player_to_move_resigns() {
+        let mut game = Game::new(Position::new(), None);
+        play(&mut game, &["e2e4"]);
+        assert!(game.resign());
+        assert!(game.status == GameStatus::Resignation(PColor::White));
+        assert!(!game.resign());
+    }
+
+    #[test]
+    fn running_out_of_time_loses() {
+        let mut game = Game::new(Position::new(), Clock::from_control("0+0"));
+        assert!(game.update_clock());
+        assert!(game.status == GameStatus::Timeout(PColor::Black));
+        assert!(!game.update_clock());
+    }
+
+    #[test]
+    fn the_clock_runs_for_the_player_to_move() {
+        let mut game = Game::new(
+            Position::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap(),
+            Clock::from_control("5+3"),
+        );
+        let clock = game.clock.as_ref().unwrap();
+        assert_eq!(clock.remaining(PColor::White), Duration::from_secs(300));
+
+        // black receives the increment, white was never charged
+        play(&mut game, &["e8d8"]);
+        let clock = game.clock.as_ref().unwrap();
+        assert!(clock.black > Duration::from_secs(300));
+        assert_eq!(clock.white, Duration::from_secs(300));
+    }
+
+    #[test]
+    fn a_clock_attached_later_runs_for_the_player_to_move() {
+        let mut game = Game::new(Position::new(), None);
+        play(&mut game, &["e2e4"]);
+        game.set_clock(Clock::from_control("5+3"));
+        play(&mut game, &["e7e5"]);
+        let clock = game.clock.as_ref().unwrap();
+        assert!(clock.black > Duration::from_secs(300));
+        assert_eq!(clock.white, Duration::from_secs(300));
+    }
+
+    const SHUFFLE: [&str; 4] = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+    #[test]
+    fn threefold_repetition_can_be_claimed() {
+        let mut game = Game::new(Position::new(), None);
+        play(&mut game, &SHUFFLE);
+        assert_eq!(game.claimable_draw(), None);
+        assert!(!game.claim_draw());
+
+        // the starting position now occurs for the third time
+        play(&mut game, &SHUFFLE);
+        assert_eq!(game.claimable_draw(), Some(DrawReason::ThreefoldRepetition));
+        assert!(game.status == GameStatus::Ongoing);
+        assert!(game.claim_draw());
+        assert!(game.status == GameStatus::Draw(DrawReason::ThreefoldRepetition));
+    }
+
+    #[test]
+    fn fivefold_repetition_ends_the_game() {
+        let mut game = Game::new(Position::new(), None);
+        for _ in 0..3 {
+            play(&mut game, &SHUFFLE);
         }
+        assert!(game.status == GameStatus::Ongoing);
+        play(&mut game, &SHUFFLE);
+        assert!(game.status == GameStatus::Draw(DrawReason::FivefoldRepetition));
     }
 
-    fn update_after_move(&mut self, start: usize, end: usize) {
-        self.switch_player();
-        self.update_last_move(start, end);
-        self.update_new_moves();
-        self.reset_hold_piece_states();
+    #[test]
+    fn move_rules_count_half_moves() {
+        let mut game = Game::new(
+            Position::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80").unwrap(),
+            None,
+        );
+        assert_eq!(game.claimable_draw(), None);
+        play(&mut game, &["a1a2"]);
+        assert_eq!(game.claimable_draw(), Some(DrawReason::FiftyMoveRule));
+        // a pawn move starts the count again
+        play(&mut game, &["e8d8", "e2e3"]);
+        assert_eq!(game.claimable_draw(), None);
+
+        let mut game = Game::new(
+            Position::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 149 120").unwrap(),
+            None,
+        );
+        assert!(game.status == GameStatus::Ongoing);
+        play(&mut game, &["a1a2"]);
+        assert!(game.status == GameStatus::Draw(DrawReason::SeventyFiveMoveRule));
     }
 
-    fn update_new_moves(&mut self) {
-        self.possible_moves = self
-            .move_generator
-            .generate_moves(&mut self.board, self.current_player);
+    #[test]
+    fn taking_the_last_piece_that_can_mate_draws() {
+        let mut game = Game::new(
+            Position::from_fen("4k3/8/8/8/8/8/3r4/4K1N1 w - - 0 1").unwrap(),
+            None,
+        );
+        assert!(game.status == GameStatus::Ongoing);
+        play(&mut game, &["e1d2"]);
+        assert!(game.status == GameStatus::Draw(DrawReason::InsufficientMaterial));
     }
 
-    fn update_last_move(&mut self, start: usize, end: usize) {
-        self.last_move = Some(Move::new(start, end));
+    #[test]
+    fn earlier_positions_are_replayed() {
+        let mut game = Game::new(Position::new(), None);
+        play(&mut game, &["e2e4", "e7e5", "g1f3"]);
+        assert_eq!(game.position_at(0).to_fen(), Position::new().to_fen());
+        assert_eq!(
+            game.position_at(2).to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+        );
+        assert_eq!(game.position_at(3).to_fen(), game.position.to_fen());
+        assert_eq!(game.position_at(10).to_fen(), game.position.to_fen());
     }
 
-    fn reset_hold_piece_states(&mut self) {
-        self.piece_hold = None;
-        self.x = 0;
-        self.y = 0;
+    #[test]
+    fn undo_restores_every_state() {
+        let mut game = Game::new(
+            Position::from_fen("r3k2r/8/8/8/3p4/8/4P3/R3K2R w KQkq - 3 20").unwrap(),
+            None,
+        );
+        let start = game.position.to_fen();
+        play(&mut game, &["e2e4", "d4e3", "e1g1"]);
+        let end = game.position.to_fen();
+
+        for _ in 0..3 {
+            assert!(game.undo().is_some());
+        }
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.position.to_fen(), start);
+        assert_eq!(game.position.hash, game.start.hash);
+        assert_eq!(game.last_move, None);
+        assert!(game.moves.is_empty());
+
+        // the moves come back in order, en passant and castling included
+        let first = game.redo().unwrap();
+        assert_eq!(first.to_string(), "e2e4");
+        assert_eq!(game.last_move, Some(first));
+        assert_eq!(
+            game.redo().map(|mv| mv.kind() == MoveKind::EnPassant),
+            Some(true)
+        );
+        assert_eq!(game.redo().map(|mv| mv.is_castle()), Some(true));
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.position.to_fen(), end);
+
+        // playing another move forgets what was taken back
+        game.undo();
+        play(&mut game, &["a1a2"]);
+        assert_eq!(game.redo(), None);
+    }
+
+    #[test]
+    fn undo_reopens_a_finished_game() {
+        let mut game = Game::new(
+            Position::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap(),
+            None,
+        );
+        play(&mut game, &["a1a8"]);
+        assert!(game.status.is_over());
+        game.undo();
+        assert!(game.status == GameStatus::Ongoing);
+        assert_eq!(game.possible_moves(square::parse("a1").unwrap()).len(), 12);
+    }
+
+    #[test]
+    fn checkmate_takes_precedence_over_the_move_rules() {
+        let mut game = Game::new(
+            Position::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 149 120").unwrap(),
+            None,
+        );
+        play(&mut game, &["a1a8"]);
+        assert!(game.status == GameStatus::Checkmate(PColor::White));
     }
 }
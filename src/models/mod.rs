@@ -1,5 +1,14 @@
+pub mod bitboard;
 pub mod board;
+pub mod clock;
+pub mod fen;
 pub mod game;
 pub mod r#move;
+pub mod perft;
+pub mod pgn;
 pub mod piece;
-pub mod sound;
+pub mod position;
+pub mod san;
+pub mod square;
+pub mod status;
+pub mod zobrist;
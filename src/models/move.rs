@@ -1,379 +1,929 @@
-use crate::common::misc;
-use crate::common::move_data::{precomputed_move_data, DIRECTION_OFFSET};
-use crate::common::move_data::{EAST, NORTH, SOUTH, WEST};
+use crate::common::move_data::DIRECTION_OFFSET;
+use crate::common::move_data::{NORTH, SOUTH};
 
+use super::bitboard::{self, Attacks, Bitboard};
 use super::board::Board;
 use super::piece::{PColor, Piece, PieceType};
+use super::position::{CastlingRights, Position};
+use super::square;
 
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Eq, PartialEq, Copy, Clone)]
-pub struct Move {
-    pub start: usize,
-    pub end: usize,
+#[derive(Eq, Hash, PartialEq, Debug, Copy, Clone)]
+pub enum MoveKind {
+    Quiet,
+    DoublePawnPush,
+    KingCastle,
+    QueenCastle,
+    Capture,
+    EnPassant,
+    Promotion,
+    PromotionCapture,
 }
 
+// A move is encoded in a single integer:
+// bits 0-5: start square, bits 6-11: end square, bits 12-14: kind,
+// bits 15-19: moved piece id, bits 20-24: captured piece id (0 if none),
+// bits 25-29: id of the piece a pawn is promoted to (0 if none)
 #[derive(Eq, Hash, PartialEq, Debug, Copy, Clone)]
-pub enum MoveAction {
-    Incorrect,
-    Move,
-    Take,
-    Castle,
-}
+pub struct Move(u32);
+
+const KINDS: [MoveKind; 8] = [
+    MoveKind::Quiet,
+    MoveKind::DoublePawnPush,
+    MoveKind::KingCastle,
+    MoveKind::QueenCastle,
+    MoveKind::Capture,
+    MoveKind::EnPassant,
+    MoveKind::Promotion,
+    MoveKind::PromotionCapture,
+];
 
 impl Move {
-    pub fn new(start: usize, end: usize) -> Move {
-        Move { start, end }
+    pub fn new(
+        start: usize,
+        end: usize,
+        kind: MoveKind,
+        piece: Piece,
+        captured: Option<Piece>,
+    ) -> Move {
+        let captured_id: u32 = captured.map_or(0, |p| p.id as u32);
+        Move(
+            start as u32
+                | (end as u32) << 6
+                | (kind as u32) << 12
+                | (piece.id as u32) << 15
+                | captured_id << 20,
+        )
     }
 
-    pub fn is_valid(
+    pub fn with_promotion(
         start: usize,
         end: usize,
-        board: &mut Board,
-        piece: &mut Piece,
-        possible_moves: &HashMap<usize, Vec<Move>>,
-    ) -> MoveAction {
-        if !Move::is_in_list(start, end, &possible_moves[&start]) {
-            return MoveAction::Incorrect;
+        piece: Piece,
+        captured: Option<Piece>,
+        promotion: PieceType,
+    ) -> Move {
+        let kind = if captured.is_some() {
+            MoveKind::PromotionCapture
+        } else {
+            MoveKind::Promotion
+        };
+        let promoted: u32 = piece.promote(promotion).id as u32;
+        Move(Move::new(start, end, kind, piece, captured).0 | promoted << 25)
+    }
+
+    pub fn start(&self) -> usize {
+        (self.0 & 0x3f) as usize
+    }
+
+    pub fn end(&self) -> usize {
+        (self.0 >> 6 & 0x3f) as usize
+    }
+
+    pub fn kind(&self) -> MoveKind {
+        KINDS[(self.0 >> 12 & 0x7) as usize]
+    }
+
+    pub fn piece(&self) -> Piece {
+        Piece::from_id((self.0 >> 15 & 0x1f) as u8).unwrap()
+    }
+
+    pub fn captured(&self) -> Option<Piece> {
+        Piece::from_id((self.0 >> 20 & 0x1f) as u8)
+    }
+
+    pub fn promotion(&self) -> Option<PieceType> {
+        Piece::from_id((self.0 >> 25 & 0x1f) as u8).map(|p| p.r#type)
+    }
+
+    pub fn is_capture(&self) -> bool {
+        matches!(
+            self.kind(),
+            MoveKind::Capture | MoveKind::EnPassant | MoveKind::PromotionCapture
+        )
+    }
+
+    pub fn is_castle(&self) -> bool {
+        matches!(self.kind(), MoveKind::KingCastle | MoveKind::QueenCastle)
+    }
+
+    // square of the piece taken by the move, which differs from the end
+    // square for en passant
+    pub fn captured_square(&self) -> usize {
+        match self.kind() {
+            MoveKind::EnPassant => self.start() - self.start() % 8 + self.end() % 8,
+            _ => self.end(),
         }
+    }
 
-        let selected = board.get_square(end);
-        let (y, x): (usize, usize) = (start / board.size, start % board.size);
-        let (i, j): (usize, usize) = (end / board.size, end % board.size);
-
-        let delta_x: i8 = x as i8 - j as i8;
-        let delta_y: i8 = y as i8 - i as i8;
-        if piece.can_castle && misc::abs(delta_x as isize) == 2 {
-            piece.can_castle = false;
-            if let PieceType::King = piece.r#type {
-                match delta_x {
-                    2 => {
-                        // we castled left side
-                        board.set(y, j + 1, board.get(y, 0));
-                        board.set(y, 0, None);
-                    }
-                    -2 => {
-                        // we castled right side
-                        board.set(y, j - 1, board.get(y, 7));
-                        board.set(y, 7, None);
-                    }
-                    a => {
-                        println!("Error: I got a delta of {} when castling!", a);
-                    }
-                }
-                board.set(i, j, Some(*piece));
-                return MoveAction::Castle;
-            }
-        } else if piece.is_type(PieceType::Pawn) {
-            if misc::abs(delta_y as isize) == 2 {
-                let mut tmp: Vec<(Option<Piece>, i8)> = Vec::new();
-                tmp.push((board.get(i, j + 1), 1));
-                if j != 0 {
-                    tmp.push((board.get(i, j - 1), -1));
-                }
+    pub fn find(
+        start: usize,
+        end: usize,
+        promotion: Option<PieceType>,
+        possible_moves: &HashMap<usize, Vec<Move>>,
+    ) -> Option<Move> {
+        let moves = possible_moves.get(&start)?;
+        moves
+            .iter()
+            .find(|mv| mv.end() == end && mv.promotion() == promotion)
+            .copied()
+    }
 
-                for (adj, abs) in tmp {
-                    match adj {
-                        None => {}
-                        Some(mut p) => {
-                            if p.is_type(PieceType::Pawn) && piece.is_enemy(adj) {
-                                p.can_en_passant = match abs {
-                                    -1 => EAST,
-                                    1 => WEST,
-                                    _ => 0,
-                                };
-                                // we update the pawn states
-                                board.set(i, (j as i8 + abs) as usize, Some(p));
-                            }
-                        }
-                    }
-                }
-            } else if misc::abs(delta_y as isize) == 1 && misc::abs(delta_x as isize) == 1 {
-                board.set(y, j, None);
-                board.set(i, j, Some(*piece));
-                return MoveAction::Take;
+    // plays the move on the board, the moved piece does not have to stand
+    // on its starting square anymore
+    pub fn apply(&self, board: &mut Board) {
+        let (start, end) = (self.start(), self.end());
+        let placed: Piece = match self.promotion() {
+            None => self.piece(),
+            Some(r#type) => self.piece().promote(r#type),
+        };
+        board.set_square(start, None);
+
+        match self.kind() {
+            MoveKind::KingCastle | MoveKind::QueenCastle => {
+                let (corner, rook_end) = if self.kind() == MoveKind::KingCastle {
+                    (start + 3, start + 1)
+                } else {
+                    (start - 4, start - 1)
+                };
+                board.set_square(rook_end, board.get_square(corner));
+                board.set_square(corner, None);
+            }
+            MoveKind::EnPassant => {
+                board.set_square(self.captured_square(), None);
             }
+            _ => {}
         }
+        board.set_square(end, Some(placed));
+    }
+}
+
+// UCI long algebraic notation: start and end squares, followed by the
+// letter of the promotion piece ("e2e4", "e7e8q", castling being "e1g1")
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let uci = UciMove {
+            start: self.start(),
+            end: self.end(),
+            promotion: self.promotion(),
+        };
+        write!(f, "{}", uci)
+    }
+}
 
-        board.set(i, j, Some(*piece));
+// A move written in UCI notation. The text alone does not say which piece
+// moves nor what kind of move it is, so it has to be found among the legal
+// moves of a position to get a `Move`.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct UciMove {
+    pub start: usize,
+    pub end: usize,
+    pub promotion: Option<PieceType>,
+}
 
-        match selected {
-            None => MoveAction::Move,
-            Some(_) => MoveAction::Take,
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum MoveError {
+    // the text does not follow the notation
+    Invalid(String),
+    // no legal move of the position matches
+    Illegal(String),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::Invalid(s) => write!(f, "'{}' is not a valid UCI move", s),
+            MoveError::Illegal(s) => write!(f, "'{}' is not a legal move", s),
         }
     }
+}
 
-    fn is_in_list(start: usize, end: usize, moves: &Vec<Move>) -> bool {
-        for r#move in moves {
-            println!("Move: from {} to {}", r#move.start, r#move.end);
-            if r#move.start == start && r#move.end == end {
-                return true;
-            }
+impl Error for MoveError {}
+
+impl UciMove {
+    pub fn find(&self, possible_moves: &HashMap<usize, Vec<Move>>) -> Option<Move> {
+        Move::find(self.start, self.end, self.promotion, possible_moves)
+    }
+}
+
+impl fmt::Display for UciMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", square::name(self.start), square::name(self.end))?;
+        match self.promotion {
+            Some(PieceType::Queen) => write!(f, "q"),
+            Some(PieceType::Rook) => write!(f, "r"),
+            Some(PieceType::Bishop) => write!(f, "b"),
+            Some(PieceType::Knight) => write!(f, "n"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for UciMove {
+    type Err = MoveError;
+
+    fn from_str(s: &str) -> Result<UciMove, MoveError> {
+        let invalid = || MoveError::Invalid(String::from(s));
+        if !s.is_ascii() || (s.len() != 4 && s.len() != 5) {
+            return Err(invalid());
         }
-        false
+        let start = square::parse(&s[0..2]).ok_or_else(invalid)?;
+        let end = square::parse(&s[2..4]).ok_or_else(invalid)?;
+        let promotion = match &s[4..] {
+            "" => None,
+            "q" => Some(PieceType::Queen),
+            "r" => Some(PieceType::Rook),
+            "b" => Some(PieceType::Bishop),
+            "n" => Some(PieceType::Knight),
+            _ => return Err(invalid()),
+        };
+        Ok(UciMove {
+            start,
+            end,
+            promotion,
+        })
     }
 }
 
 pub struct MoveGenerator {
-    precomputed: [[i8; 8]; 64],
+    attacks: &'static Attacks,
+}
+
+impl Default for MoveGenerator {
+    fn default() -> Self {
+        MoveGenerator::new()
+    }
 }
 
 impl MoveGenerator {
     pub fn new() -> MoveGenerator {
-        let precomputed = precomputed_move_data();
-
-        MoveGenerator { precomputed }
+        MoveGenerator {
+            attacks: Attacks::get(),
+        }
     }
 
-    pub fn generate_moves(
-        &self,
-        board: &mut Board,
-        player_color: PColor,
-    ) -> HashMap<usize, Vec<Move>> {
+    pub fn generate_moves(&self, position: &mut Position) -> HashMap<usize, Vec<Move>> {
+        let player_color = position.current_player;
+        let castling = position.castling;
+        let en_passant = position.en_passant;
+        let board = &position.board;
         let mut hash = HashMap::new();
-        for square in 0..64 {
+        for square in bitboard::squares(board.color(player_color)) {
             let mut moves: Vec<Move> = Vec::new();
-            let mut piece = match board.get_square(square) {
+            let piece = match board.get_square(square) {
                 None => continue,
                 Some(p) => p,
             };
-            if !piece.is_color(player_color) {
-                continue;
-            }
 
-            if piece.is_sliding_piece() {
-                self.generate_sliding_move(&mut moves, &piece, square, board);
-            } else {
-                match piece.r#type {
-                    PieceType::Pawn => {
-                        if self.generate_pawn_move(&mut moves, &mut piece, square, board) {
-                            board.set_square(square, Some(piece));
-                        }
-                    }
-                    PieceType::Knight => {
-                        self.generate_knight_move(&mut moves, &piece, square, board);
-                    }
-                    PieceType::King => {
-                        self.generate_king_move(&mut moves, &piece, square, board);
+            match piece.r#type {
+                PieceType::Pawn => {
+                    self.generate_pawn_move(&mut moves, &piece, square, board, en_passant);
+                }
+                PieceType::King => {
+                    self.generate_king_move(&mut moves, &piece, square, board, castling);
+                }
+                _ => {
+                    let targets = self.piece_attacks(&piece, square, board.occupied())
+                        & !board.color(player_color);
+                    for target in bitboard::squares(targets) {
+                        moves.push(MoveGenerator::basic_move(&piece, square, target, board));
                     }
-                    _ => {}
                 }
             }
             hash.insert(square, moves);
         }
+
+        // pseudo-legal moves leaving our own king in check are discarded
+        for moves in hash.values_mut() {
+            moves.retain(|mv| self.is_legal(mv, position));
+        }
         hash
     }
 
-    fn generate_sliding_move(
-        &self,
-        moves: &mut Vec<Move>,
-        piece: &Piece,
-        square: usize,
-        board: &Board,
-    ) {
-        let start_index: i32 = if piece.r#type == PieceType::Bishop {
-            4
-        } else {
-            0
-        };
-        let end_index: i32 = if piece.r#type == PieceType::Rook {
-            4
-        } else {
-            8
-        };
+    pub fn is_in_check(&self, board: &Board, color: PColor) -> bool {
+        match board.king_square(color) {
+            None => false,
+            Some(square) => self.is_square_attacked(square, board, color.opposite()),
+        }
+    }
 
-        for index in start_index..end_index {
-            for n in 0..self.precomputed[square][index as usize] {
-                let target = (square as i8 + (DIRECTION_OFFSET[index as usize] * (n + 1))) as usize;
-                let s = board.get_square(target);
-                if s.is_some() {
-                    if piece.is_enemy(s) {
-                        moves.push(Move::new(square, target));
-                    }
-                    break;
-                } else {
-                    moves.push(Move::new(square, target));
-                }
+    pub fn is_square_attacked(&self, square: usize, board: &Board, attacker: PColor) -> bool {
+        let occupied = board.occupied();
+        let pieces = |r#type: PieceType| board.pieces(r#type, attacker);
+        let queens = pieces(PieceType::Queen);
+
+        // pawns attack diagonally towards the enemy side, so we look for them
+        // where a pawn of the other color would attack from the square
+        self.attacks.pawn(attacker.opposite(), square) & pieces(PieceType::Pawn) != 0
+            || self.attacks.knight(square) & pieces(PieceType::Knight) != 0
+            || self.attacks.king(square) & pieces(PieceType::King) != 0
+            || self.attacks.bishop(square, occupied) & (pieces(PieceType::Bishop) | queens) != 0
+            || self.attacks.rook(square, occupied) & (pieces(PieceType::Rook) | queens) != 0
+    }
+
+    // -------------------------------------------
+    // ------------ PRIVATE FUNCTIONS ------------
+    // -------------------------------------------
+
+    fn is_legal(&self, mv: &Move, position: &mut Position) -> bool {
+        let player_color = position.current_player;
+        let enemy = player_color.opposite();
+        if mv.is_castle() {
+            // the king can neither castle out of check nor through an attacked square
+            let passing = (mv.start() + mv.end()) / 2;
+            if self.is_square_attacked(mv.start(), &position.board, enemy)
+                || self.is_square_attacked(passing, &position.board, enemy)
+            {
+                return false;
             }
         }
+
+        position.make_move(*mv);
+        let legal = !self.is_in_check(&position.board, player_color);
+        position.unmake_move();
+        legal
+    }
+
+    // squares attacked by a knight or a sliding piece
+    fn piece_attacks(&self, piece: &Piece, square: usize, occupied: Bitboard) -> Bitboard {
+        match piece.r#type {
+            PieceType::Knight => self.attacks.knight(square),
+            PieceType::Bishop => self.attacks.bishop(square, occupied),
+            PieceType::Rook => self.attacks.rook(square, occupied),
+            PieceType::Queen => self.attacks.queen(square, occupied),
+            _ => 0,
+        }
     }
+
+    // quiet move or capture, depending on what stands on the target square
+    fn basic_move(piece: &Piece, square: usize, target: usize, board: &Board) -> Move {
+        let captured = board.get_square(target);
+        let kind = if captured.is_some() {
+            MoveKind::Capture
+        } else {
+            MoveKind::Quiet
+        };
+        Move::new(square, target, kind, *piece, captured)
+    }
+
     fn generate_pawn_move(
         &self,
         moves: &mut Vec<Move>,
-        piece: &mut Piece,
+        piece: &Piece,
         square: usize,
         board: &Board,
-    ) -> bool {
-        let max_squares_forward = match piece.color {
-            PColor::White => {
-                if square / 8 == 6 {
-                    // it is on the seventh row
-                    2
-                } else {
-                    1
-                }
-            }
-            PColor::Black => {
-                if square / 8 == 1 {
-                    // it is on the second row
-                    2
-                } else {
-                    1
-                }
-            }
-        };
-        let (direction, range_column, diag_left, diag_right) = if piece.is_color(PColor::White) {
-            (
-                DIRECTION_OFFSET[NORTH],
-                self.precomputed[square][NORTH],
-                4,
-                6,
-            )
-        } else {
-            (
-                DIRECTION_OFFSET[SOUTH],
-                self.precomputed[square][SOUTH],
-                7,
-                5,
-            )
+        en_passant: Option<usize>,
+    ) {
+        // pawns can move two squares from their starting row
+        let (direction, start_row): (i8, usize) = match piece.color {
+            PColor::White => (DIRECTION_OFFSET[NORTH], 6),
+            PColor::Black => (DIRECTION_OFFSET[SOUTH], 1),
         };
+        let max_squares_forward = if square / 8 == start_row { 2 } else { 1 };
 
-        if range_column == 0 {
-            return false;
-        }
-
+        let occupied = board.occupied();
         for n in 0..max_squares_forward {
-            let target = square as i8 + direction * (n + 1);
-            if board.get_square(target as usize).is_some() {
+            let target = (square as i8 + direction * (n + 1)) as usize;
+            if occupied & bitboard::bit(target) != 0 {
                 break;
             }
-            moves.push(Move::new(square, target as usize));
-        }
-
-        for diag in [diag_left, diag_right] {
-            if self.precomputed[square][diag] > 0 {
-                let pos: usize = (square as i8 + DIRECTION_OFFSET[diag]) as usize;
-                if piece.is_enemy(board.get_square(pos)) {
-                    moves.push(Move::new(square, pos))
-                }
+            if n == 1 {
+                moves.push(Move::new(
+                    square,
+                    target,
+                    MoveKind::DoublePawnPush,
+                    *piece,
+                    None,
+                ));
+            } else {
+                MoveGenerator::push_pawn_move(moves, piece, square, target, board);
             }
         }
-        self.__generate_en_passant_move(moves, piece, direction, square, board)
-    }
 
-    fn __generate_en_passant_move(
-        &self,
-        moves: &mut Vec<Move>,
-        piece: &mut Piece,
-        direction: i8,
-        square: usize,
-        _board: &Board,
-    ) -> bool {
-        if piece.can_en_passant == 0 {
-            return false;
+        let captures = self.attacks.pawn(piece.color, square) & board.color(piece.color.opposite());
+        for target in bitboard::squares(captures) {
+            MoveGenerator::push_pawn_move(moves, piece, square, target, board);
         }
-
-        match piece.can_en_passant {
-            WEST => {
-                moves.push(Move::new(square, (square as i8 + direction - 1) as usize));
-            }
-            EAST => {
-                moves.push(Move::new(square, (square as i8 + direction + 1) as usize));
-            }
-            _ => {}
-        }
-        piece.can_en_passant = 0;
-        true
+        self.__generate_en_passant_move(moves, piece, square, board, en_passant);
     }
 
-    fn generate_king_move(
-        &self,
+    fn push_pawn_move(
         moves: &mut Vec<Move>,
         piece: &Piece,
         square: usize,
+        target: usize,
         board: &Board,
     ) {
-        for (index, item) in DIRECTION_OFFSET.iter().enumerate() {
-            if self.precomputed[square][index] != 0 {
-                let end: usize = (square as i8 + item) as usize;
-                if !piece.is_ally(board.get_square(end)) {
-                    moves.push(Move::new(square, end));
-                }
+        // reaching the first or last row means the pawn has to be promoted
+        if target / 8 == 0 || target / 8 == 7 {
+            let captured = board.get_square(target);
+            for r#type in [
+                PieceType::Queen,
+                PieceType::Rook,
+                PieceType::Bishop,
+                PieceType::Knight,
+            ] {
+                moves.push(Move::with_promotion(
+                    square, target, *piece, captured, r#type,
+                ));
             }
+        } else {
+            moves.push(MoveGenerator::basic_move(piece, square, target, board));
         }
-        self.__generate_castling_moves(moves, piece, square, board);
     }
 
-    fn __generate_castling_moves(
+    // the square skipped by a pawn pushing two squares on the previous move
+    // can be taken for one ply only, by a pawn attacking it
+    fn __generate_en_passant_move(
         &self,
         moves: &mut Vec<Move>,
         piece: &Piece,
         square: usize,
         board: &Board,
+        en_passant: Option<usize>,
     ) {
-        if !piece.can_castle {
+        let target = match en_passant {
+            None => return,
+            Some(target) => target,
+        };
+        if self.attacks.pawn(piece.color, square) & bitboard::bit(target) == 0 {
             return;
         }
-        for direction in [WEST, EAST] {
-            let mut can_castle: bool = true;
-            let sign: i8 = match direction {
-                WEST => -1,
-                EAST => 1,
-                _ => 0,
-            };
-            for index in 0..(self.precomputed[square][direction] - 1) {
-                let target: i8 = square as i8 + sign * (index + 1);
-                if board.get_square(target as usize).is_some() {
-                    can_castle = false;
-                    break;
-                }
-            }
-            let y: usize = square / 8;
-            let pair = board.get(y, if direction == WEST { 0 } else { 7 });
-            if can_castle && Piece::can_castle(pair) {
-                let end: usize = (square as i8 + (sign * 2)) as usize;
-                moves.push(Move::new(square, end));
+        // the pushed pawn stands next to ours, behind the skipped square
+        let captured = match board.get_square(square - square % 8 + target % 8) {
+            Some(captured)
+                if captured.is_type(PieceType::Pawn)
+                    && captured.is_color(piece.color.opposite()) =>
+            {
+                captured
             }
-        }
+            _ => return,
+        };
+        moves.push(Move::new(
+            square,
+            target,
+            MoveKind::EnPassant,
+            *piece,
+            Some(captured),
+        ));
     }
 
-    fn generate_knight_move(
+    fn generate_king_move(
         &self,
         moves: &mut Vec<Move>,
         piece: &Piece,
         square: usize,
         board: &Board,
+        castling: CastlingRights,
     ) {
-        for row in [-1, 1] {
-            for column in [-8, 8] {
-                let target1: i32 = (square as i32) + row + (column * 2);
-                let target2: i32 = (square as i32) + (row * 2) + column;
-                self.__generate_knight_move(moves, piece, square, target1, board);
-                self.__generate_knight_move(moves, piece, square, target2, board);
-            }
+        let targets = self.attacks.king(square) & !board.color(piece.color);
+        for target in bitboard::squares(targets) {
+            moves.push(MoveGenerator::basic_move(piece, square, target, board));
         }
+        self.__generate_castling_moves(moves, piece, square, board, castling);
     }
 
-    fn __generate_knight_move(
+    // squares the king goes through are checked for attacks in `is_legal`
+    fn __generate_castling_moves(
         &self,
         moves: &mut Vec<Move>,
         piece: &Piece,
         square: usize,
-        target: i32,
         board: &Board,
+        castling: CastlingRights,
     ) {
-        if target >= 0 && target < (board.size * board.size) as i32 {
-            let t: usize = target as usize;
-            let col_start: usize = square % board.size;
-            let col_end: usize = t % board.size;
-
-            if (col_start < 2 && col_end > 5) || (col_start > 5 && col_end < 2) {
-                return;
+        // the rights say whether the king and the rook have already moved
+        let home: usize = match piece.color {
+            PColor::White => 60,
+            PColor::Black => 4,
+        };
+        if square != home {
+            return;
+        }
+        for (allowed, corner, kind) in [
+            (
+                castling.king_side(piece.color),
+                home + 3,
+                MoveKind::KingCastle,
+            ),
+            (
+                castling.queen_side(piece.color),
+                home - 4,
+                MoveKind::QueenCastle,
+            ),
+        ] {
+            if !allowed {
+                continue;
             }
-
-            if !piece.is_ally(board.get_square(t)) {
-                moves.push(Move::new(square, t));
+            // a rook of the same color still stands in the corner
+            match board.get_square(corner) {
+                Some(p) if p.is_type(PieceType::Rook) && p.is_color(piece.color) => {}
+                _ => continue,
+            }
+            // every square between the king and the rook must be empty
+            let between: Bitboard = (square.min(corner) + 1..square.max(corner))
+                .fold(0, |set, target| set | bitboard::bit(target));
+            if between & board.occupied() != 0 {
+                continue;
             }
+            let end: usize = if kind == MoveKind::KingCastle {
+                square + 2
+            } else {
+                square - 2
+            };
+            moves.push(Move::new(square, end, kind, *piece, None));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position_from(notation: &str) -> Position {
+        Position::from_fen(notation).unwrap()
+    }
+
+    fn targets(moves: &HashMap<usize, Vec<Move>>, from: &str) -> Vec<usize> {
+        let mut ends: Vec<usize> = moves[&square::parse(from).unwrap()]
+            .iter()
+            .map(|mv| mv.end())
+            .collect();
+        ends.sort();
+        ends
+    }
+
+    fn squares(names: &[&str]) -> Vec<usize> {
+        let mut result: Vec<usize> = names
+            .iter()
+            .map(|name| square::parse(name).unwrap())
+            .collect();
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn pinned_rook_stays_on_the_pin_line() {
+        let mut position = position_from("4k3/8/8/8/4r3/8/4R3/4K3 w - - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+
+        assert_eq!(targets(&moves, "e2"), squares(&["e3", "e4"]));
+    }
+
+    #[test]
+    fn pinned_knight_cannot_move() {
+        let mut position = position_from("4k3/8/8/b7/8/8/3N4/4K3 w - - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+
+        assert!(targets(&moves, "d2").is_empty());
+    }
+
+    #[test]
+    fn king_cannot_walk_into_attacked_square() {
+        let mut position = position_from("4k3/8/8/8/8/8/3r4/4K3 w - - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+
+        assert_eq!(targets(&moves, "e1"), squares(&["d2", "f1"]));
+    }
+
+    #[test]
+    fn single_check_must_be_answered() {
+        // the bishop can block on e2, the rook can capture on e4
+        let mut position = position_from("4k3/8/8/8/R3r3/8/8/3BK3 w - - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+
+        assert_eq!(targets(&moves, "d1"), squares(&["e2"]));
+        assert_eq!(targets(&moves, "a4"), squares(&["e4"]));
+        assert_eq!(targets(&moves, "e1"), squares(&["d2", "f1", "f2"]));
+    }
+
+    #[test]
+    fn double_check_only_allows_king_moves() {
+        // rook and knight both give check: capturing or blocking one is not enough
+        let mut position = position_from("4k3/8/8/8/4r3/3n4/2Q5/4K3 w - - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+
+        assert!(targets(&moves, "c2").is_empty());
+        assert_eq!(targets(&moves, "e1"), squares(&["d1", "d2", "f1"]));
+    }
+
+    #[test]
+    fn discovered_check_on_own_king_is_illegal() {
+        // the bishop shields its king from the queen on the diagonal
+        let mut position = position_from("4k3/8/8/8/7q/8/5B2/4K3 w - - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+
+        assert_eq!(targets(&moves, "f2"), squares(&["g3", "h4"]));
+    }
+
+    #[test]
+    fn en_passant_cannot_uncover_check_along_the_rank() {
+        let mut position = position_from("8/8/8/KPp4r/8/8/8/4k3 w - c6 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+
+        assert_eq!(targets(&moves, "b5"), squares(&["b6"]));
+    }
+
+    #[test]
+    fn castling_through_or_out_of_check_is_illegal() {
+        let mut position = position_from("4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+        let king = targets(&moves, "e1");
+        assert!(king.contains(&square::parse("c1").unwrap()));
+        assert!(!king.contains(&square::parse("g1").unwrap()));
+
+        let mut position = position_from("4r1k1/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+        let king = targets(&moves, "e1");
+        assert!(!king.contains(&square::parse("c1").unwrap()));
+        assert!(!king.contains(&square::parse("g1").unwrap()));
+    }
+
+    #[test]
+    fn castling_into_check_is_illegal_but_the_rook_may_be_attacked() {
+        // g1 and c1 are attacked, b1 and the rooks are not an issue
+        let mut position = position_from("1r2k3/8/8/8/8/8/6r1/R3K2R w KQ - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+        let king = targets(&moves, "e1");
+        assert!(!king.contains(&square::parse("g1").unwrap()));
+        assert!(king.contains(&square::parse("c1").unwrap()));
+
+        let mut position = position_from("4k3/8/8/8/8/8/2r5/R3K2R w KQ - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+        assert!(!targets(&moves, "e1").contains(&square::parse("c1").unwrap()));
+    }
+
+    #[test]
+    fn castling_needs_the_right_and_an_own_rook_in_the_corner() {
+        // no right left on the king side
+        let mut position = position_from("4k3/8/8/8/8/8/8/R3K2R w Q - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+        assert_eq!(
+            targets(&moves, "e1"),
+            squares(&["c1", "d1", "d2", "e2", "f1", "f2"])
+        );
+
+        // the corner holds an enemy rook or a knight
+        for notation in [
+            "4k3/8/8/8/8/8/8/r3K2n w KQ - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w KQ - 0 1",
+        ] {
+            let mut position = position_from(notation);
+            let moves = MoveGenerator::new().generate_moves(&mut position);
+            let king = targets(&moves, "e1");
+            assert!(
+                !king.contains(&square::parse("c1").unwrap())
+                    && !king.contains(&square::parse("g1").unwrap())
+            );
+        }
+
+        // a piece stands between the king and the rook
+        let mut position = position_from("4k3/8/8/8/8/8/8/RN2K1NR w KQ - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+        let king = targets(&moves, "e1");
+        assert!(
+            !king.contains(&square::parse("c1").unwrap())
+                && !king.contains(&square::parse("g1").unwrap())
+        );
+    }
+
+    #[test]
+    fn moving_the_rook_loses_its_side_only() {
+        let generator = MoveGenerator::new();
+        let mut position = position_from("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let moves = generator.generate_moves(&mut position);
+        let rook = Move::find(
+            square::parse("h1").unwrap(),
+            square::parse("h2").unwrap(),
+            None,
+            &moves,
+        )
+        .unwrap();
+        position.make_move(rook);
+        let moves = generator.generate_moves(&mut position);
+        let rook = Move::find(
+            square::parse("h8").unwrap(),
+            square::parse("h7").unwrap(),
+            None,
+            &moves,
+        )
+        .unwrap();
+        position.make_move(rook);
+
+        let moves = generator.generate_moves(&mut position);
+        let king = targets(&moves, "e1");
+        assert!(
+            king.contains(&square::parse("c1").unwrap())
+                && !king.contains(&square::parse("g1").unwrap())
+        );
+        assert!(!position.castling.white_king_side && position.castling.white_queen_side);
+    }
+
+    #[test]
+    fn en_passant_is_only_available_for_one_ply() {
+        let generator = MoveGenerator::new();
+        let mut position = position_from("4k3/3p4/8/4P3/8/8/8/R3K3 b - - 0 1");
+        let moves = generator.generate_moves(&mut position);
+        position.make_move(
+            Move::find(
+                square::parse("d7").unwrap(),
+                square::parse("d5").unwrap(),
+                None,
+                &moves,
+            )
+            .unwrap(),
+        );
+        assert_eq!(position.en_passant, Some(square::parse("d6").unwrap()));
+
+        let moves = generator.generate_moves(&mut position);
+        assert_eq!(targets(&moves, "e5"), squares(&["d6", "e6"]));
+
+        // white plays something else, the chance is gone
+        position.make_move(
+            Move::find(
+                square::parse("a1").unwrap(),
+                square::parse("a2").unwrap(),
+                None,
+                &moves,
+            )
+            .unwrap(),
+        );
+        let moves = generator.generate_moves(&mut position);
+        position.make_move(
+            Move::find(
+                square::parse("e8").unwrap(),
+                square::parse("e7").unwrap(),
+                None,
+                &moves,
+            )
+            .unwrap(),
+        );
+        let moves = generator.generate_moves(&mut position);
+        assert_eq!(targets(&moves, "e5"), squares(&["e6"]));
+    }
+
+    #[test]
+    fn en_passant_only_takes_an_enemy_pawn() {
+        // en passant squares the notation would refuse
+        for placement in ["4k3/8/8/3PP3/8/8/8/4K3", "4k3/8/8/4P3/8/8/8/4K3"] {
+            let mut position = position_from(&format!("{} w - - 0 1", placement));
+            position.en_passant = Some(square::parse("d6").unwrap());
+            let moves = MoveGenerator::new().generate_moves(&mut position);
+
+            assert_eq!(targets(&moves, "e5"), squares(&["e6"]));
+        }
+    }
+
+    #[test]
+    fn generation_leaves_the_position_untouched() {
+        let generator = MoveGenerator::new();
+        let mut position =
+            position_from("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3");
+        let squares = position.board.squares().to_vec();
+
+        let first = generator.generate_moves(&mut position);
+        let second = generator.generate_moves(&mut position);
+        assert_eq!(first, second);
+        assert!(targets(&first, "e5").contains(&square::parse("f6").unwrap()));
+        assert!(position.board.squares() == squares);
+        assert_eq!(
+            position.to_fen(),
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3"
+        );
+    }
+
+    #[test]
+    fn uci_notation_round_trips() {
+        let mut position = position_from("r3k3/1P6/8/3pP3/8/8/8/4K2R w Kq d6 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+        for mv in moves.values().flatten() {
+            assert_eq!(position.parse_uci(&mv.to_string()), Ok(*mv));
+        }
+
+        let promotion = position.parse_uci("b7a8n").unwrap();
+        assert_eq!(promotion.promotion(), Some(PieceType::Knight));
+        assert_eq!(promotion.to_string(), "b7a8n");
+        assert_eq!(
+            position.parse_uci("e1g1").unwrap().kind(),
+            MoveKind::KingCastle
+        );
+        assert_eq!(
+            position.parse_uci("e5d6").unwrap().kind(),
+            MoveKind::EnPassant
+        );
+    }
+
+    #[test]
+    fn uci_notation_is_checked() {
+        let mut position = position_from("r3k3/1P6/8/3pP3/8/8/8/4K2R w Kq d6 0 1");
+        for text in ["", "e2", "e2e4e", "e2e9", "b7a8k", "b7a8Q"] {
+            assert_eq!(
+                position.parse_uci(text),
+                Err(MoveError::Invalid(String::from(text)))
+            );
         }
+        // a promotion needs its piece
+        for text in ["b7a8", "e1e3"] {
+            assert_eq!(
+                position.parse_uci(text),
+                Err(MoveError::Illegal(String::from(text)))
+            );
+        }
+        assert_eq!(
+            "e7e8q".parse(),
+            Ok(UciMove {
+                start: square::parse("e7").unwrap(),
+                end: square::parse("e8").unwrap(),
+                promotion: Some(PieceType::Queen),
+            })
+        );
+    }
+
+    #[test]
+    fn pawns_reaching_the_last_row_are_promoted() {
+        // white pushes to c8 or takes on b8, black pushes to g1
+        let mut position = position_from("1n2k3/2P5/8/8/8/8/6p1/4K3 w - - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+        for to in ["c8", "b8"] {
+            let mut promotions: Vec<PieceType> = moves[&square::parse("c7").unwrap()]
+                .iter()
+                .filter(|mv| mv.end() == square::parse(to).unwrap())
+                .map(|mv| mv.promotion().unwrap())
+                .collect();
+            promotions.sort_by_key(|r#type| *r#type as usize);
+            assert_eq!(
+                promotions,
+                [
+                    PieceType::Knight,
+                    PieceType::Bishop,
+                    PieceType::Rook,
+                    PieceType::Queen
+                ]
+            );
+        }
+        assert_eq!(moves[&square::parse("c7").unwrap()].len(), 8);
+
+        let mut position = position_from("1n2k3/2P5/8/8/8/8/6p1/4K3 b - - 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+        assert_eq!(moves[&square::parse("g2").unwrap()].len(), 4);
+        assert!(
+            moves[&square::parse("g2").unwrap()]
+                .iter()
+                .all(|mv| mv.end() == square::parse("g1").unwrap()
+                    && mv.kind() == MoveKind::Promotion)
+        );
+    }
+
+    #[test]
+    fn promoted_pieces_keep_their_color() {
+        let white = Piece::new('P').unwrap();
+        let black = Piece::new('p').unwrap();
+        for r#type in [
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Rook,
+            PieceType::Queen,
+        ] {
+            assert!(white.promote(r#type).is_color(PColor::White));
+            assert!(white.promote(r#type).is_type(r#type));
+            assert!(black.promote(r#type).is_color(PColor::Black));
+            assert!(black.promote(r#type).is_type(r#type));
+        }
+    }
+
+    #[test]
+    fn moves_carry_their_kind_and_pieces() {
+        let mut position = position_from("r3k3/1P6/8/3pP3/8/8/8/4K2R w Kq d6 0 1");
+        let moves = MoveGenerator::new().generate_moves(&mut position);
+        let find = |from: &str, to: &str| {
+            moves[&square::parse(from).unwrap()]
+                .iter()
+                .find(|mv| mv.end() == square::parse(to).unwrap())
+                .copied()
+                .unwrap()
+        };
+
+        let castle = find("e1", "g1");
+        assert_eq!(castle.kind(), MoveKind::KingCastle);
+        assert!(castle.piece().is_type(PieceType::King) && castle.captured().is_none());
+
+        let en_passant = find("e5", "d6");
+        assert_eq!(en_passant.kind(), MoveKind::EnPassant);
+        assert_eq!(en_passant.captured_square(), square::parse("d5").unwrap());
+        assert!(en_passant.captured().unwrap().is_type(PieceType::Pawn));
+
+        let promotion = find("b7", "a8");
+        assert_eq!(promotion.kind(), MoveKind::PromotionCapture);
+        assert_eq!(promotion.promotion(), Some(PieceType::Queen));
+        assert!(promotion.captured().unwrap().is_type(PieceType::Rook));
+        assert!(promotion.piece().is_color(PColor::White));
+
+        assert_eq!(find("e1", "e2").kind(), MoveKind::Quiet);
+        assert_eq!(find("h1", "h8").kind(), MoveKind::Quiet);
+        assert_eq!(moves[&square::parse("b7").unwrap()].len(), 8);
     }
 }
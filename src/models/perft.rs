@@ -0,0 +1,157 @@
+use super::position::Position;
+use super::r#move::{Move, MoveGenerator};
+
+// counts the leaf nodes of the legal move tree down to `depth`
+pub fn perft(position: &mut Position, generator: &MoveGenerator, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = generator.generate_moves(position);
+    if depth == 1 {
+        return moves.values().map(|m| m.len() as u64).sum();
+    }
+
+    let mut nodes: u64 = 0;
+    for mv in moves.into_values().flatten() {
+        position.make_move(mv);
+        nodes += perft(position, generator, depth - 1);
+        position.unmake_move();
+    }
+    nodes
+}
+
+// perft split by the first move, useful to find which branch is wrong,
+// empty at depth 0 as no move is played
+pub fn divide(position: &mut Position, generator: &MoveGenerator, depth: u32) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    let mut moves: Vec<Move> = generator
+        .generate_moves(position)
+        .into_values()
+        .flatten()
+        .collect();
+    moves.sort_by_key(|mv| (mv.start(), mv.end()));
+
+    let mut result: Vec<(Move, u64)> = Vec::new();
+    for mv in moves {
+        position.make_move(mv);
+        let nodes = perft(position, generator, depth - 1);
+        position.unmake_move();
+        result.push((mv, nodes));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(notation: &str, expected: &[u64]) {
+        let generator = MoveGenerator::new();
+        let mut position = Position::from_fen(notation).unwrap();
+        for (depth, nodes) in expected.iter().enumerate() {
+            assert_eq!(
+                perft(&mut position, &generator, depth as u32 + 1),
+                *nodes,
+                "depth {} of {}",
+                depth + 1,
+                notation
+            );
+        }
+        assert_eq!(position.to_fen(), notation);
+    }
+
+    #[test]
+    fn start_position() {
+        check(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197281],
+        );
+    }
+
+    #[test]
+    fn kiwipete() {
+        check(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862],
+        );
+    }
+
+    #[test]
+    fn position_3() {
+        check(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238],
+        );
+    }
+
+    #[test]
+    fn position_4() {
+        check(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467],
+        );
+    }
+
+    #[test]
+    fn position_4_mirrored() {
+        check(
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            &[6, 264, 9467],
+        );
+    }
+
+    #[test]
+    fn position_5() {
+        check(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379],
+        );
+    }
+
+    #[test]
+    fn position_6() {
+        check(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89890],
+        );
+    }
+
+    #[test]
+    fn divide_splits_the_count() {
+        let generator = MoveGenerator::new();
+        let mut position = Position::new();
+        assert!(divide(&mut position, &generator, 0).is_empty());
+
+        let split = divide(&mut position, &generator, 3);
+        assert_eq!(split.len(), 20);
+        assert_eq!(split.iter().map(|(_, nodes)| nodes).sum::<u64>(), 8902);
+    }
+
+    // deeper counts, too slow for every test run: cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn deep_positions() {
+        check(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862, 4085603],
+        );
+        check(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238, 674624],
+        );
+        check(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467, 422333],
+        );
+        check(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379, 2103487],
+        );
+        check(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89890, 3894594],
+        );
+    }
+}
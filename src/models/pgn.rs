@@ -0,0 +1,736 @@
+use super::fen::{self, FenError};
+use super::game::Game;
+use super::piece::PColor;
+use super::position::Position;
+use super::r#move::Move;
+use super::san::SanError;
+
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::time::Duration;
+use std::vec::IntoIter;
+
+// longest line of the movetext, as advised by the PGN standard
+const LINE_LENGTH: usize = 80;
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum PgnError {
+    // the text breaks the PGN syntax, e.g. an unclosed comment
+    Syntax(String),
+    // the FEN tag of the game (counted from 1) is not valid
+    InvalidFen(usize, FenError),
+    // a move of the game (counted from 1) cannot be played
+    InvalidMove(usize, SanError),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Syntax(s) => write!(f, "invalid PGN: {}", s),
+            PgnError::InvalidFen(game, error) => write!(f, "game {}: invalid FEN: {}", game, error),
+            PgnError::InvalidMove(game, error) => write!(f, "game {}: {}", game, error),
+        }
+    }
+}
+
+impl Error for PgnError {}
+
+// A move of an imported game, with its annotations and the variations
+// suggested in its place. Each variation starts from the position before
+// the move.
+#[derive(Debug, Clone)]
+pub struct MoveNode {
+    pub mv: Move,
+    pub san: String,
+    // numeric annotation glyphs, "!" being read as $1, "?" as $2 and so on
+    pub nags: Vec<u8>,
+    // comments written before and after the move
+    pub comment_before: Option<String>,
+    pub comment: Option<String>,
+    pub variations: Vec<Vec<MoveNode>>,
+}
+
+pub struct PgnGame {
+    // tag pairs, in the order they were read
+    pub tags: Vec<(String, String)>,
+    // position of the FEN tag, or the usual starting position
+    pub start: Position,
+    // main line of the game
+    pub moves: Vec<MoveNode>,
+    // "1-0", "0-1", "1/2-1/2" or "*"
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // the game played along the main line, without clock
+    pub fn replay(&self) -> Game {
+        let mut game = Game::new(self.start.clone(), None);
+        for node in &self.moves {
+            // moves played after the game is over are left out
+            if !game.play_move(node.mv) {
+                break;
+            }
+        }
+        game
+    }
+}
+
+// The seven tag roster every PGN game starts with, the result being taken
+// from the game itself. "?" stands for an unknown value.
+pub struct Header {
+    pub event: String,
+    pub site: String,
+    // written as "YYYY.MM.DD"
+    pub date: String,
+    pub round: String,
+    pub white: String,
+    pub black: String,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Header::new()
+    }
+}
+
+impl Header {
+    // header of a casual game played today
+    pub fn new() -> Header {
+        Header {
+            event: String::from("Casual game"),
+            site: String::from("DRW Chess"),
+            date: chrono::Local::now().format("%Y.%m.%d").to_string(),
+            round: String::from("-"),
+            white: String::from("?"),
+            black: String::from("?"),
+        }
+    }
+}
+
+// the game in PGN, with the time left after each move as a comment when
+// `clock_comments` is set and the game is played with a clock
+pub fn write(game: &Game, header: &Header, clock_comments: bool) -> String {
+    let result = game.status.result();
+    let mut pgn = String::new();
+    for (name, value) in [
+        ("Event", header.event.as_str()),
+        ("Site", &header.site),
+        ("Date", &header.date),
+        ("Round", &header.round),
+        ("White", &header.white),
+        ("Black", &header.black),
+        ("Result", result),
+    ] {
+        pgn.push_str(&tag(name, value));
+    }
+    // games that do not start from the usual position carry their own
+    let start = game.start.to_fen();
+    if start != fen::START_POSITION {
+        pgn.push_str(&tag("SetUp", "1"));
+        pgn.push_str(&tag("FEN", &start));
+    }
+    if let Some(clock) = &game.clock {
+        let control = format!("{}+{}", clock.base.as_secs(), clock.increment.as_secs());
+        pgn.push_str(&tag("TimeControl", &control));
+    }
+    pgn.push('\n');
+
+    let mut tokens: Vec<String> = Vec::new();
+    let mut number = game.start.fullmove_number;
+    let mut player = game.start.current_player;
+    // whether the last token written is a comment
+    let mut commented = false;
+    for (index, played) in game.moves.iter().enumerate() {
+        match player {
+            PColor::White => tokens.push(format!("{}.", number)),
+            // the first move, or the one following a comment, is black's
+            PColor::Black if index == 0 || commented => tokens.push(format!("{}...", number)),
+            PColor::Black => {}
+        }
+        tokens.push(played.san.clone());
+        commented = false;
+        if let (true, Some(left)) = (clock_comments, played.clock) {
+            tokens.push(format!("{{[%clk {}]}}", clock_time(left)));
+            commented = true;
+        }
+        if player == PColor::Black {
+            number += 1;
+        }
+        player = player.opposite();
+    }
+    tokens.push(String::from(result));
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    pgn.push_str(&line);
+    pgn.push_str("\n\n");
+    pgn
+}
+
+// every game of the text, moves being checked as they are read
+pub fn parse(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut tokens = tokenize(text)?.into_iter().peekable();
+    let mut games = Vec::new();
+    loop {
+        // comments between games go to the next one
+        let mut comment: Option<String> = None;
+        while let Some(Token::Comment(_)) = tokens.peek() {
+            if let Some(Token::Comment(text)) = tokens.next() {
+                append_comment(&mut comment, &text);
+            }
+        }
+        match tokens.peek() {
+            None => break,
+            Some(Token::Tag(..)) | Some(Token::San(_)) => {}
+            Some(_) => return Err(PgnError::Syntax(String::from("game without tags or moves"))),
+        }
+        let number = games.len() + 1;
+        let mut tags = Vec::new();
+        while let Some(Token::Tag(..)) = tokens.peek() {
+            if let Some(Token::Tag(name, value)) = tokens.next() {
+                tags.push((name, value));
+            }
+        }
+        let start = match tags.iter().find(|(name, _)| name == "FEN") {
+            None => Position::new(),
+            Some((_, notation)) => {
+                Position::from_fen(notation).map_err(|error| PgnError::InvalidFen(number, error))?
+            }
+        };
+        let mut position = start.clone();
+        let mut moves = parse_line(&mut tokens, &mut position, number, 0)?;
+        if let (Some(comment), Some(first)) = (comment, moves.first_mut()) {
+            let mut before = Some(comment);
+            if let Some(text) = first.comment_before.take() {
+                append_comment(&mut before, &text);
+            }
+            first.comment_before = before;
+        }
+        // the result may be missing before the next game or the end
+        let result = match tokens.peek() {
+            Some(Token::Result(_)) => match tokens.next() {
+                Some(Token::Result(result)) => result,
+                _ => unreachable!(),
+            },
+            _ => String::from("*"),
+        };
+        games.push(PgnGame {
+            tags,
+            start,
+            moves,
+            result,
+        });
+    }
+    Ok(games)
+}
+
+// -------------------------------------------
+// ------------ PRIVATE FUNCTIONS ------------
+// -------------------------------------------
+
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    // start and end of a variation
+    Open,
+    Close,
+    San(String),
+    Result(String),
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+// moves of a line with their annotations, up to the end of the variation
+// when `depth` is not 0, or of the game otherwise
+fn parse_line(
+    tokens: &mut Tokens,
+    position: &mut Position,
+    game: usize,
+    depth: usize,
+) -> Result<Vec<MoveNode>, PgnError> {
+    let mut line: Vec<MoveNode> = Vec::new();
+    // position before the last move of the line, where variations start
+    let mut before: Option<Position> = None;
+    let mut pending_comment: Option<String> = None;
+    loop {
+        match tokens.peek() {
+            None | Some(Token::Result(_)) | Some(Token::Tag(..)) => {
+                if depth > 0 {
+                    return Err(PgnError::Syntax(String::from("unclosed variation")));
+                }
+                return Ok(line);
+            }
+            Some(Token::Close) => {
+                if depth == 0 {
+                    return Err(PgnError::Syntax(String::from("unexpected ')'")));
+                }
+                tokens.next();
+                return Ok(line);
+            }
+            _ => {}
+        }
+        match tokens.next() {
+            Some(Token::Comment(text)) => match line.last_mut() {
+                Some(node) => append_comment(&mut node.comment, &text),
+                None => append_comment(&mut pending_comment, &text),
+            },
+            Some(Token::Nag(nag)) => match line.last_mut() {
+                Some(node) => node.nags.push(nag),
+                None => return Err(PgnError::Syntax(String::from("annotation before any move"))),
+            },
+            Some(Token::Open) => match (line.last_mut(), &before) {
+                (Some(node), Some(before)) => {
+                    let mut start = before.clone();
+                    let variation = parse_line(tokens, &mut start, game, depth + 1)?;
+                    node.variations.push(variation);
+                }
+                _ => return Err(PgnError::Syntax(String::from("variation before any move"))),
+            },
+            Some(Token::San(text)) => {
+                let mv = position
+                    .parse_san(&text)
+                    .map_err(|error| PgnError::InvalidMove(game, error))?;
+                let san = position.to_san(mv);
+                before = Some(position.clone());
+                position.make_move(mv);
+                line.push(MoveNode {
+                    mv,
+                    san,
+                    nags: Vec::new(),
+                    comment_before: pending_comment.take(),
+                    comment: None,
+                    variations: Vec::new(),
+                });
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn append_comment(comment: &mut Option<String>, text: &str) {
+    match comment {
+        None => *comment = Some(String::from(text)),
+        Some(comment) => {
+            comment.push(' ');
+            comment.push_str(text);
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let line_start = i == 0 || chars[i - 1] == '\n';
+        i += 1;
+        match c {
+            _ if c.is_whitespace() => {}
+            // escaped lines are meant for other programs
+            '%' if line_start => i = skip_line(&chars, i),
+            ';' => {
+                let end = skip_line(&chars, i);
+                let comment: String = chars[i..end].iter().collect();
+                tokens.push(Token::Comment(String::from(comment.trim())));
+                i = end;
+            }
+            '{' => {
+                let end = match chars[i..].iter().position(|c| *c == '}') {
+                    None => return Err(PgnError::Syntax(String::from("unclosed comment"))),
+                    Some(length) => i + length,
+                };
+                let comment: String = chars[i..end].iter().collect();
+                tokens.push(Token::Comment(String::from(comment.trim())));
+                i = end + 1;
+            }
+            '[' => {
+                let (tag, end) = read_tag(&chars, i)?;
+                tokens.push(tag);
+                i = end;
+            }
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => {
+                let end = symbol_end(&chars, i);
+                let digits: String = chars[i..end].iter().collect();
+                match digits.parse() {
+                    Ok(nag) => tokens.push(Token::Nag(nag)),
+                    Err(_) => {
+                        return Err(PgnError::Syntax(format!(
+                            "invalid annotation '${}'",
+                            digits
+                        )))
+                    }
+                }
+                i = end;
+            }
+            _ => {
+                let end = symbol_end(&chars, i);
+                let symbol: String = chars[i - 1..end].iter().collect();
+                read_symbol(&symbol, &mut tokens)?;
+                i = end;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// index of the end of the line starting at i
+fn skip_line(chars: &[char], i: usize) -> usize {
+    chars[i..]
+        .iter()
+        .position(|c| *c == '\n')
+        .map_or(chars.len(), |length| i + length)
+}
+
+fn symbol_end(chars: &[char], i: usize) -> usize {
+    chars[i..]
+        .iter()
+        .position(|c| c.is_whitespace() || "{}()[];$".contains(*c))
+        .map_or(chars.len(), |length| i + length)
+}
+
+// reads a tag pair such as [Event "F/S Return Match"] starting after the
+// opening bracket, returns it with the index following the closing bracket
+fn read_tag(chars: &[char], mut i: usize) -> Result<(Token, usize), PgnError> {
+    let invalid = || PgnError::Syntax(String::from("invalid tag pair"));
+    let skip_spaces = |mut i: usize| {
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+        i
+    };
+
+    i = skip_spaces(i);
+    let mut name = String::new();
+    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+        name.push(chars[i]);
+        i += 1;
+    }
+    i = skip_spaces(i);
+    if name.is_empty() || chars.get(i) != Some(&'"') {
+        return Err(invalid());
+    }
+    i += 1;
+
+    let mut value = String::new();
+    loop {
+        match chars.get(i) {
+            None => return Err(invalid()),
+            Some('"') => break,
+            Some('\\') => {
+                value.push(*chars.get(i + 1).ok_or_else(invalid)?);
+                i += 2;
+            }
+            Some(c) => {
+                value.push(*c);
+                i += 1;
+            }
+        }
+    }
+    i = skip_spaces(i + 1);
+    if chars.get(i) != Some(&']') {
+        return Err(invalid());
+    }
+    Ok((Token::Tag(name, value), i + 1))
+}
+
+// a move, possibly preceded by its number and followed by "!" or "?"
+// annotations, or the result of the game
+fn read_symbol(symbol: &str, tokens: &mut Vec<Token>) -> Result<(), PgnError> {
+    if ["1-0", "0-1", "1/2-1/2", "*"].contains(&symbol) {
+        tokens.push(Token::Result(String::from(symbol)));
+        return Ok(());
+    }
+
+    // "12.", "12..." or "12.e4", while castling may be written "0-0"
+    let mut san = symbol;
+    let unnumbered = symbol.trim_start_matches(|c: char| c.is_ascii_digit());
+    if unnumbered.len() < symbol.len() && unnumbered.starts_with('.') {
+        san = unnumbered.trim_start_matches('.');
+    }
+    if san.is_empty() {
+        return Ok(());
+    }
+
+    let move_text = san.trim_end_matches(['!', '?']);
+    let nag = match &san[move_text.len()..] {
+        "" => None,
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        suffix => return Err(PgnError::Syntax(format!("invalid annotation '{}'", suffix))),
+    };
+    if move_text.is_empty() {
+        return Err(PgnError::Syntax(format!("invalid symbol '{}'", symbol)));
+    }
+    tokens.push(Token::San(String::from(move_text)));
+    if let Some(nag) = nag {
+        tokens.push(Token::Nag(nag));
+    }
+    Ok(())
+}
+
+fn tag(name: &str, value: &str) -> String {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("[{} \"{}\"]\n", name, value)
+}
+
+// "H:MM:SS", as read by most PGN tools
+fn clock_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::clock::Clock;
+    use crate::models::status::GameStatus;
+
+    fn header() -> Header {
+        Header {
+            event: String::from("Test"),
+            site: String::from("Here"),
+            date: String::from("2024.01.31"),
+            round: String::from("1"),
+            white: String::from("Alice"),
+            black: String::from("Bob \"The Rook\""),
+        }
+    }
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for text in moves {
+            let mv = game.position.parse_san(text).unwrap();
+            assert!(game.play_move(mv));
+        }
+    }
+
+    #[test]
+    fn writes_the_roster_and_the_moves() {
+        let mut game = Game::new(Position::new(), None);
+        play(&mut game, &["f3", "e5", "g4", "Qh4"]);
+
+        assert_eq!(
+            write(&game, &header(), false),
+            "[Event \"Test\"]\n\
+             [Site \"Here\"]\n\
+             [Date \"2024.01.31\"]\n\
+             [Round \"1\"]\n\
+             [White \"Alice\"]\n\
+             [Black \"Bob \\\"The Rook\\\"\"]\n\
+             [Result \"0-1\"]\n\
+             \n\
+             1. f3 e5 2. g4 Qh4# 0-1\n\n"
+        );
+    }
+
+    #[test]
+    fn custom_start_and_clock_comments() {
+        let start = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 30";
+        let clock = Clock::from_control("5+3");
+        let mut game = Game::new(Position::from_fen(start).unwrap(), clock);
+        play(&mut game, &["Kd7", "e4"]);
+
+        let pgn = write(&game, &header(), true);
+        assert!(pgn.contains("[Result \"*\"]\n[SetUp \"1\"]\n"));
+        assert!(pgn.contains(&format!("[FEN \"{}\"]\n", start)));
+        assert!(pgn.contains("[TimeControl \"300+3\"]\n"));
+        // the times left depend on how long the test takes
+        let movetext = pgn.split("\n\n").nth(1).unwrap();
+        let tokens: Vec<&str> = movetext.split_whitespace().collect();
+        assert_eq!(tokens[..2], ["30...", "Kd7"]);
+        assert_eq!(tokens[2], "{[%clk");
+        assert!(tokens[3].starts_with("0:0") && tokens[3].ends_with("]}"));
+        assert_eq!(tokens[4..6], ["31.", "e4"]);
+        assert_eq!(tokens.last(), Some(&"*"));
+
+        // without comments, black's first move still needs its number
+        let pgn = write(&game, &header(), false);
+        assert!(pgn.ends_with("\n30... Kd7 31. e4 *\n\n"));
+    }
+
+    #[test]
+    fn moves_without_clock_have_no_comment() {
+        // the clock is only set once the first moves are played
+        let mut game = Game::new(Position::new(), None);
+        play(&mut game, &["e4", "e5"]);
+        game.set_clock(Clock::from_control("5+3"));
+        play(&mut game, &["Nf3", "Nc6"]);
+
+        let pgn = write(&game, &header(), true);
+        let movetext = pgn.split("\n\n").nth(1).unwrap();
+        let tokens: Vec<&str> = movetext.split_whitespace().collect();
+        assert_eq!(tokens[..5], ["1.", "e4", "e5", "2.", "Nf3"]);
+        assert_eq!(tokens[7..9], ["2...", "Nc6"]);
+    }
+
+    #[test]
+    fn clock_comments_show_hours() {
+        assert_eq!(clock_time(Duration::from_secs(5)), "0:00:05");
+        assert_eq!(clock_time(Duration::from_millis(303_900)), "0:05:03");
+        assert_eq!(clock_time(Duration::from_secs(5400)), "1:30:00");
+    }
+
+    const ARCHIVE: &str = r#"
+% exported from the club database
+[Event "F/S Return Match"]
+[Site "Belgrade, Serbia JUG"]
+[Date "1992.11.04"]
+[Round "29"]
+[White "Fischer, Robert J."]
+[Black "Spassky, Boris V."]
+[Result "1/2-1/2"]
+[Annotator "A \"quoted\" name"]
+
+{Opening comment} 1. e4 e5 2. Nf3 Nc6 3. Bb5 {This opening is called the Ruy Lopez.}
+3... a6 $1 (3...Nf6 4. O-O (4. d3 {quiet}) 4...Nxe4; the open defence
+) 4.Ba4 Nf6!? 5. O-O Be7 6. Re1 b5 7. Bb3 d6?! 8. c3 O-O 1/2-1/2
+
+[Event "Scholar"]
+[SetUp "1"]
+[FEN "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 2 3"]
+
+3. Qxf7#
+"#;
+
+    #[test]
+    fn reads_several_games_with_annotations() {
+        let games = parse(ARCHIVE).unwrap();
+        assert_eq!(games.len(), 2);
+
+        let game = &games[0];
+        assert_eq!(game.tag("White"), Some("Fischer, Robert J."));
+        assert_eq!(game.tag("Annotator"), Some("A \"quoted\" name"));
+        assert_eq!(game.tag("ECO"), None);
+        assert_eq!(game.result, "1/2-1/2");
+        assert_eq!(game.moves.len(), 16);
+        assert_eq!(
+            game.moves[0].comment_before.as_deref(),
+            Some("Opening comment")
+        );
+        assert_eq!(
+            game.moves[4].comment.as_deref(),
+            Some("This opening is called the Ruy Lopez.")
+        );
+        assert_eq!(game.moves[5].nags, [1]);
+        assert_eq!(game.moves[7].nags, [5]);
+        assert_eq!(game.moves[13].nags, [6]);
+
+        // 3...Nf6 replaces 3...a6, and itself holds a variation on move 4
+        let variation = &game.moves[5].variations[0];
+        let sans: Vec<&str> = variation.iter().map(|node| node.san.as_str()).collect();
+        assert_eq!(sans, ["Nf6", "O-O", "Nxe4"]);
+        assert_eq!(variation[1].variations[0][0].san, "d3");
+        assert_eq!(
+            variation[1].variations[0][0].comment.as_deref(),
+            Some("quiet")
+        );
+        assert_eq!(variation[2].comment.as_deref(), Some("the open defence"));
+
+        let replayed = games[0].replay();
+        assert_eq!(replayed.moves.len(), 16);
+        assert_eq!(
+            replayed.position.to_fen(),
+            "r1bq1rk1/2p1bppp/p1np1n2/1p2p3/4P3/1BP2N2/PP1P1PPP/RNBQR1K1 w - - 1 9"
+        );
+
+        let scholar = &games[1];
+        assert_eq!(scholar.result, "*");
+        assert_eq!(scholar.moves[0].san, "Qxf7#");
+        assert!(scholar.replay().status == GameStatus::Checkmate(PColor::White));
+    }
+
+    #[test]
+    fn comments_outside_games_make_no_game() {
+        let games = parse("1. e4 e5 1-0 {end}\n[Event \"B\"]\n1. d4 {first} d5 *").unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[1].tag("Event"), Some("B"));
+        assert_eq!(games[1].moves[0].san, "d4");
+        assert_eq!(games[1].moves[0].comment_before.as_deref(), Some("end"));
+        assert_eq!(games[1].moves[0].comment.as_deref(), Some("first"));
+
+        let games = parse("1. e4 e5 1-0\n{trailing} ; and another\n").unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].result, "1-0");
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let mut game = Game::new(Position::new(), None);
+        play(&mut game, &["e4", "c5", "Nf3", "d6", "d4", "cxd4", "Nxd4"]);
+        let games = parse(&write(&game, &header(), false)).unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].tag("Black"), Some("Bob \"The Rook\""));
+        let sans: Vec<&str> = games[0]
+            .moves
+            .iter()
+            .map(|node| node.san.as_str())
+            .collect();
+        assert_eq!(sans, ["e4", "c5", "Nf3", "d6", "d4", "cxd4", "Nxd4"]);
+    }
+
+    #[test]
+    fn rejects_broken_text() {
+        let syntax = |text: &str| matches!(parse(text), Err(PgnError::Syntax(_)));
+        assert!(syntax("1. e4 {never closed"));
+        assert!(syntax("1. e4 (1. d4 *"));
+        assert!(syntax("1. e4 e5) *"));
+        assert!(syntax("(1. d4) 1. e4 *"));
+        assert!(syntax("[Event \"Unclosed] 1. e4 *"));
+        assert!(syntax("$3 1. e4 *"));
+
+        assert!(matches!(
+            parse("1. e4 e5 2. Ke3 *"),
+            Err(PgnError::InvalidMove(1, SanError::Illegal(_)))
+        ));
+        assert!(matches!(
+            parse("1. e4 *\n\n[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n\n1. e4 *"),
+            Err(PgnError::InvalidFen(2, _))
+        ));
+        assert_eq!(parse("").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn long_games_are_wrapped() {
+        // the Opera game, Morphy against the Duke and the Count
+        let mut game = Game::new(Position::new(), None);
+        let moves = "e4 e5 Nf3 d6 d4 Bg4 dxe5 Bxf3 Qxf3 dxe5 Bc4 Nf6 Qb3 Qe7 Nc3 c6 \
+                     Bg5 b5 Nxb5 cxb5 Bxb5+ Nbd7 O-O-O Rd8 Rxd7 Rxd7 Rd1 Qe6 Bxd7+ \
+                     Nxd7 Qb8+ Nxb8 Rd8#";
+        play(&mut game, &moves.split_whitespace().collect::<Vec<&str>>());
+        let pgn = write(&game, &header(), false);
+        let movetext = pgn.split("\n\n").nth(1).unwrap();
+        assert!(movetext.lines().count() > 1);
+        assert!(movetext.ends_with("17. Rd8# 1-0"));
+        assert!(movetext.lines().all(|line| line.len() <= LINE_LENGTH));
+    }
+}
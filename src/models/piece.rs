@@ -1,11 +1,8 @@
 use crate::common::misc;
 
-use sdl2::image::LoadTexture;
-use sdl2::render::Texture;
-use sdl2::render::TextureCreator;
-use sdl2::video::WindowContext;
-
-use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Hash, Eq, PartialEq, Debug, Copy, Clone)]
 pub enum PieceType {
@@ -17,25 +14,92 @@ pub enum PieceType {
     King,
 }
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub enum PColor {
     White,
     Black,
 }
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+impl PColor {
+    pub fn opposite(&self) -> PColor {
+        match self {
+            PColor::White => PColor::Black,
+            PColor::Black => PColor::White,
+        }
+    }
+}
+
+// the text given to `FromStr` does not name a piece, a piece type or a color
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct ParsePieceError(pub String);
+
+impl fmt::Display for ParsePieceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not a valid piece, piece type or color", self.0)
+    }
+}
+
+impl Error for ParsePieceError {}
+
+// "Knight", read back from the name or from the letter in either case
+impl fmt::Display for PieceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            PieceType::Pawn => "Pawn",
+            PieceType::Knight => "Knight",
+            PieceType::Bishop => "Bishop",
+            PieceType::Rook => "Rook",
+            PieceType::Queen => "Queen",
+            PieceType::King => "King",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for PieceType {
+    type Err = ParsePieceError;
+
+    fn from_str(s: &str) -> Result<PieceType, ParsePieceError> {
+        let r#type = match s.to_lowercase().as_str() {
+            "p" | "pawn" => PieceType::Pawn,
+            "n" | "knight" => PieceType::Knight,
+            "b" | "bishop" => PieceType::Bishop,
+            "r" | "rook" => PieceType::Rook,
+            "q" | "queen" => PieceType::Queen,
+            "k" | "king" => PieceType::King,
+            _ => return Err(ParsePieceError(String::from(s))),
+        };
+        Ok(r#type)
+    }
+}
+
+// "White", read back from the name or from the FEN letter in either case
+impl fmt::Display for PColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PColor::White => write!(f, "White"),
+            PColor::Black => write!(f, "Black"),
+        }
+    }
+}
+
+impl FromStr for PColor {
+    type Err = ParsePieceError;
+
+    fn from_str(s: &str) -> Result<PColor, ParsePieceError> {
+        match s.to_lowercase().as_str() {
+            "w" | "white" => Ok(PColor::White),
+            "b" | "black" => Ok(PColor::Black),
+            _ => Err(ParsePieceError(String::from(s))),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub struct Piece {
     pub r#type: PieceType, // state's name is type
     pub color: PColor,
     pub id: u8,
-    pub can_castle: bool,
-    pub can_en_passant: usize,
-}
-
-pub struct PieceTextures<'a> {
-    // renderer: &'a TextureCreator<WindowContext>,
-    pub black_textures: HashMap<PieceType, Texture<'a>>,
-    pub white_textures: HashMap<PieceType, Texture<'a>>,
 }
 
 impl Piece {
@@ -60,54 +124,55 @@ impl Piece {
             r#type: t.0,
             color,
             id,
-            can_castle: t.0 == PieceType::King || t.0 == PieceType::Rook,
-            can_en_passant: 0,
         })
     }
 
-    pub fn create_piece_textures(renderer: &TextureCreator<WindowContext>) -> PieceTextures<'_> {
-        let tmp_black = HashMap::from([
-            (PieceType::Pawn, "textures/pieces/black_pawn.png"),
-            (PieceType::Knight, "textures/pieces/black_knight.png"),
-            (PieceType::Bishop, "textures/pieces/black_bishop.png"),
-            (PieceType::Rook, "textures/pieces/black_rook.png"),
-            (PieceType::Queen, "textures/pieces/black_queen.png"),
-            (PieceType::King, "textures/pieces/black_king.png"),
-        ]);
-
-        let tmp_white = HashMap::from([
-            (PieceType::Pawn, "textures/pieces/white_pawn.png"),
-            (PieceType::Knight, "textures/pieces/white_knight.png"),
-            (PieceType::Bishop, "textures/pieces/white_bishop.png"),
-            (PieceType::Rook, "textures/pieces/white_rook.png"),
-            (PieceType::Queen, "textures/pieces/white_queen.png"),
-            (PieceType::King, "textures/pieces/white_king.png"),
-        ]);
-
-        let mut white: HashMap<PieceType, Texture> = HashMap::new();
-        let mut black: HashMap<PieceType, Texture> = HashMap::new();
-
-        for (piece, path) in tmp_black {
-            black.insert(piece, renderer.load_texture(path).unwrap());
-            println!("Loaded piece at path {}", path);
-        }
-
-        for (piece, path) in tmp_white {
-            white.insert(piece, renderer.load_texture(path).unwrap());
-            println!("Loaded piece at path {}", path);
+    // inverse of the id computed by `Piece::new`
+    pub fn from_id(id: u8) -> Option<Piece> {
+        let symbol = match id & 7 {
+            1 => 'p',
+            2 => 'n',
+            3 => 'b',
+            4 => 'r',
+            5 => 'k',
+            6 => 'q',
+            _ => return None,
+        };
+        match id & 24 {
+            16 => Piece::new(symbol.to_ascii_uppercase()),
+            8 => Piece::new(symbol),
+            _ => None,
         }
+    }
 
-        PieceTextures {
-            //renderer: renderer,
-            black_textures: black,
-            white_textures: white,
-        }
+    pub fn promote(&self, r#type: PieceType) -> Piece {
+        let symbol = match r#type {
+            PieceType::Knight => 'n',
+            PieceType::Bishop => 'b',
+            PieceType::Rook => 'r',
+            _ => 'q',
+        };
+        let symbol = match self.color {
+            PColor::White => symbol.to_ascii_uppercase(),
+            PColor::Black => symbol,
+        };
+        Piece::new(symbol).unwrap()
     }
 
-    pub fn is_sliding_piece(&self) -> bool {
-        self.r#type == PieceType::Queen
-            || self.r#type == PieceType::Bishop
-            || self.r#type == PieceType::Rook
+    // letter used by FEN, uppercase for white
+    pub fn symbol(&self) -> char {
+        let symbol = match self.r#type {
+            PieceType::Pawn => 'p',
+            PieceType::Knight => 'n',
+            PieceType::Bishop => 'b',
+            PieceType::Rook => 'r',
+            PieceType::Queen => 'q',
+            PieceType::King => 'k',
+        };
+        match self.color {
+            PColor::White => symbol.to_ascii_uppercase(),
+            PColor::Black => symbol,
+        }
     }
 
     pub fn is_type(&self, r#type: PieceType) -> bool {
@@ -117,25 +182,68 @@ impl Piece {
     pub fn is_color(&self, color: PColor) -> bool {
         self.color == color
     }
+}
+
+// the FEN letter of the piece, "N" for a white knight
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
 
-    pub fn is_enemy(&self, piece: Option<Piece>) -> bool {
-        match piece {
-            None => false,
-            Some(p) => p.color != self.color,
+impl FromStr for Piece {
+    type Err = ParsePieceError;
+
+    fn from_str(s: &str) -> Result<Piece, ParsePieceError> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(symbol), None) => Piece::new(symbol).ok_or(ParsePieceError(String::from(s))),
+            _ => Err(ParsePieceError(String::from(s))),
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_round_trips() {
+        for symbol in ["P", "n", "B", "r", "Q", "k"] {
+            let piece: Piece = symbol.parse().unwrap();
+            assert_eq!(piece.to_string(), symbol);
+        }
+        assert_eq!("N".parse::<Piece>().unwrap().r#type, PieceType::Knight);
+        assert_eq!("q".parse::<Piece>().unwrap().color, PColor::Black);
+
+        for r#type in [
+            PieceType::Pawn,
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Rook,
+            PieceType::Queen,
+            PieceType::King,
+        ] {
+            assert_eq!(r#type.to_string().parse(), Ok(r#type));
+        }
+        assert_eq!("n".parse(), Ok(PieceType::Knight));
+        assert_eq!("QUEEN".parse(), Ok(PieceType::Queen));
 
-    pub fn is_ally(&self, piece: Option<Piece>) -> bool {
-        match piece {
-            None => false,
-            Some(p) => p.color == self.color,
+        for color in [PColor::White, PColor::Black] {
+            assert_eq!(color.to_string().parse(), Ok(color));
         }
+        assert_eq!("b".parse(), Ok(PColor::Black));
     }
 
-    pub fn can_castle(piece: Option<Piece>) -> bool {
-        match piece {
-            None => false,
-            Some(p) => p.can_castle,
+    #[test]
+    fn rejects_unknown_text() {
+        for text in ["", "x", "Nn", "1"] {
+            assert_eq!(
+                text.parse::<Piece>(),
+                Err(ParsePieceError(String::from(text)))
+            );
         }
+        assert!("knightt".parse::<PieceType>().is_err());
+        assert!("red".parse::<PColor>().is_err());
     }
 }
@@ -0,0 +1,345 @@
+use super::board::Board;
+use super::fen::{self, FenError};
+use super::piece::{PColor, Piece, PieceType};
+use super::r#move::{Move, MoveError, MoveGenerator, MoveKind, UciMove};
+use super::san::{self, SanError};
+use super::zobrist::Zobrist;
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct CastlingRights {
+    pub white_king_side: bool,
+    pub white_queen_side: bool,
+    pub black_king_side: bool,
+    pub black_queen_side: bool,
+}
+
+impl CastlingRights {
+    pub fn none() -> CastlingRights {
+        CastlingRights {
+            white_king_side: false,
+            white_queen_side: false,
+            black_king_side: false,
+            black_queen_side: false,
+        }
+    }
+
+    pub fn king_side(&self, color: PColor) -> bool {
+        match color {
+            PColor::White => self.white_king_side,
+            PColor::Black => self.black_king_side,
+        }
+    }
+
+    pub fn queen_side(&self, color: PColor) -> bool {
+        match color {
+            PColor::White => self.white_queen_side,
+            PColor::Black => self.black_queen_side,
+        }
+    }
+
+    // a right is lost as soon as something leaves or lands on the square of
+    // the king or of the corresponding rook
+    pub fn update(&mut self, start: usize, end: usize) {
+        for square in [start, end] {
+            match square {
+                0 => self.black_queen_side = false,
+                4 => {
+                    self.black_king_side = false;
+                    self.black_queen_side = false;
+                }
+                7 => self.black_king_side = false,
+                56 => self.white_queen_side = false,
+                60 => {
+                    self.white_king_side = false;
+                    self.white_queen_side = false;
+                }
+                63 => self.white_king_side = false,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Position {
+    // piece placement
+    pub board: Board,
+    // color of the player that has to play the next move
+    pub current_player: PColor,
+    pub castling: CastlingRights,
+    // square a pawn skipped over with its double step on the previous move
+    pub en_passant: Option<usize>,
+    // half moves since the last capture or pawn move
+    pub halfmove_clock: u32,
+    // starts at 1 and is incremented after each move of black
+    pub fullmove_number: u32,
+    // zobrist key of the position, updated by make and unmake
+    pub hash: u64,
+    // everything needed to take back the moves made so far, last one on top
+    history: Vec<Undo>,
+}
+
+// states of the position before a move was made
+#[derive(Clone)]
+struct Undo {
+    mv: Move,
+    // touched squares with their content at the time
+    squares: Vec<(usize, Option<Piece>)>,
+    castling: CastlingRights,
+    en_passant: Option<usize>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    hash: u64,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position::new()
+    }
+}
+
+impl Position {
+    pub fn new() -> Position {
+        Position::from_fen(fen::START_POSITION).unwrap()
+    }
+
+    pub fn from_fen(notation: &str) -> Result<Position, FenError> {
+        fen::parse(notation)
+    }
+
+    pub fn from_states(
+        board: Board,
+        current_player: PColor,
+        castling: CastlingRights,
+        en_passant: Option<usize>,
+        halfmove_clock: u32,
+        fullmove_number: u32,
+    ) -> Position {
+        let mut position = Position {
+            board,
+            current_player,
+            castling,
+            en_passant,
+            halfmove_clock,
+            fullmove_number,
+            hash: 0,
+            history: Vec::new(),
+        };
+        position.hash = Zobrist::get().hash(&position);
+        position
+    }
+
+    pub fn to_fen(&self) -> String {
+        fen::write(self)
+    }
+
+    // standard algebraic notation of a legal move of the position
+    pub fn to_san(&mut self, mv: Move) -> String {
+        san::write(self, mv)
+    }
+
+    pub fn parse_san(&mut self, text: &str) -> Result<Move, SanError> {
+        san::parse(self, text)
+    }
+
+    // finds the legal move of the position written in UCI notation
+    pub fn parse_uci(&mut self, text: &str) -> Result<Move, MoveError> {
+        let uci: UciMove = text.parse()?;
+        let moves = MoveGenerator::new().generate_moves(self);
+        uci.find(&moves)
+            .ok_or_else(|| MoveError::Illegal(String::from(text)))
+    }
+
+    // plays a move generated for the current player, it can be taken back
+    // with `unmake_move`
+    pub fn make_move(&mut self, mv: Move) {
+        let squares: Vec<(usize, Option<Piece>)> = Position::touched_squares(&mv)
+            .into_iter()
+            .map(|square| (square, self.board.get_square(square)))
+            .collect();
+        // the keys of everything that may change are taken out of the hash,
+        // and those of the new position put back once the move is played
+        let zobrist = Zobrist::get();
+        let mut hash = self.hash ^ self.state_keys();
+        for (square, piece) in &squares {
+            if let Some(p) = piece {
+                hash ^= zobrist.piece(*p, *square);
+            }
+        }
+        self.history.push(Undo {
+            mv,
+            squares,
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            hash: self.hash,
+        });
+
+        mv.apply(&mut self.board);
+        self.update_states(&mv);
+
+        for (square, _) in &self.history.last().unwrap().squares {
+            if let Some(p) = self.board.get_square(*square) {
+                hash ^= zobrist.piece(p, *square);
+            }
+        }
+        self.hash = hash ^ self.state_keys();
+    }
+
+    // takes back the last move made, returning it
+    pub fn unmake_move(&mut self) -> Option<Move> {
+        let undo = self.history.pop()?;
+        for (square, piece) in undo.squares.iter().rev() {
+            self.board.set_square(*square, *piece);
+        }
+        self.castling = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.fullmove_number = undo.fullmove_number;
+        self.hash = undo.hash;
+        self.current_player = self.current_player.opposite();
+        Some(undo.mv)
+    }
+
+    // number of times the current position occurred, itself included; only
+    // the moves since the last capture or pawn move can lead back to it
+    pub fn repetitions(&self) -> usize {
+        1 + self
+            .history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            .filter(|undo| undo.hash == self.hash)
+            .count()
+    }
+
+    // -------------------------------------------
+    // ------------ PRIVATE FUNCTIONS ------------
+    // -------------------------------------------
+
+    // keys of the castling rights, en passant file and side to move
+    fn state_keys(&self) -> u64 {
+        let zobrist = Zobrist::get();
+        zobrist.castling(self.castling)
+            ^ zobrist.en_passant(self)
+            ^ zobrist.side(self.current_player)
+    }
+
+    // every square `Move::apply` may change for the given move
+    fn touched_squares(mv: &Move) -> Vec<usize> {
+        let (start, end) = (mv.start(), mv.end());
+        let mut squares = vec![start, end];
+        match mv.kind() {
+            MoveKind::KingCastle => squares.extend([start + 1, start + 3]),
+            MoveKind::QueenCastle => squares.extend([start - 1, start - 4]),
+            MoveKind::EnPassant => squares.push(mv.captured_square()),
+            _ => {}
+        }
+        squares
+    }
+
+    // updates the position states once the move has been played on the board
+    fn update_states(&mut self, mv: &Move) {
+        self.castling.update(mv.start(), mv.end());
+
+        self.en_passant = match mv.kind() {
+            MoveKind::DoublePawnPush => Some((mv.start() + mv.end()) / 2),
+            _ => None,
+        };
+
+        if mv.piece().is_type(PieceType::Pawn) || mv.captured().is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if self.current_player == PColor::Black {
+            self.fullmove_number += 1;
+        }
+        self.current_player = self.current_player.opposite();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // makes and unmakes every move down to `depth`, checking that each
+    // take back restores the position exactly
+    fn check_undo(position: &mut Position, generator: &MoveGenerator, depth: u32) {
+        if depth == 0 {
+            return;
+        }
+        let moves: Vec<Move> = generator
+            .generate_moves(position)
+            .into_values()
+            .flatten()
+            .collect();
+        for mv in moves {
+            let fen = position.to_fen();
+            let squares = position.board.squares().to_vec();
+
+            position.make_move(mv);
+            // the incremental key must match the one computed from scratch
+            assert_eq!(position.hash, Zobrist::get().hash(position));
+            check_undo(position, generator, depth - 1);
+            assert!(position.unmake_move() == Some(mv));
+
+            assert_eq!(position.to_fen(), fen);
+            assert!(position.board.squares() == squares);
+            assert_eq!(position.hash, Zobrist::get().hash(position));
+        }
+    }
+
+    #[test]
+    fn unmake_restores_the_position() {
+        let generator = MoveGenerator::new();
+        for notation in [
+            fen::START_POSITION,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let mut position = Position::from_fen(notation).unwrap();
+            check_undo(&mut position, &generator, 2);
+        }
+    }
+
+    #[test]
+    fn make_updates_the_states() {
+        let generator = MoveGenerator::new();
+        let mut position = Position::from_fen("r3k2r/8/8/8/4p3/8/3P4/R3K2R w KQkq - 5 10").unwrap();
+        let moves = generator.generate_moves(&mut position);
+        let find = |start: usize, end: usize| {
+            moves[&start]
+                .iter()
+                .find(|mv| mv.end() == end)
+                .copied()
+                .unwrap()
+        };
+
+        // d2-d4, skipping d3
+        position.make_move(find(51, 35));
+        assert_eq!(
+            position.to_fen(),
+            "r3k2r/8/8/8/3Pp3/8/8/R3K2R b KQkq d3 0 10"
+        );
+        // the black king walks, losing both castling rights
+        position.make_move(Move::new(
+            4,
+            5,
+            MoveKind::Quiet,
+            position.board.get_square(4).unwrap(),
+            None,
+        ));
+        assert_eq!(position.to_fen(), "r4k1r/8/8/8/3Pp3/8/8/R3K2R w KQ - 1 11");
+
+        assert!(position.unmake_move().is_some());
+        assert!(position.unmake_move().is_some());
+        assert!(position.unmake_move().is_none());
+        assert_eq!(
+            position.to_fen(),
+            "r3k2r/8/8/8/4p3/8/3P4/R3K2R w KQkq - 5 10"
+        );
+    }
+}
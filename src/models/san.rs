@@ -0,0 +1,324 @@
+use super::piece::PieceType;
+use super::position::Position;
+use super::r#move::{Move, MoveGenerator, MoveKind};
+use super::square::{self, file, rank};
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum SanError {
+    // the text does not follow the notation
+    Invalid(String),
+    // no legal move matches the text
+    Illegal(String),
+    // several legal moves match the text
+    Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::Invalid(s) => write!(f, "'{}' is not a valid move notation", s),
+            SanError::Illegal(s) => write!(f, "'{}' is not a legal move", s),
+            SanError::Ambiguous(s) => write!(f, "'{}' matches several moves", s),
+        }
+    }
+}
+
+impl Error for SanError {}
+
+// standard algebraic notation of a legal move of the position, such as
+// "Nbd7", "exd8=Q+" or "O-O-O#"
+pub fn write(position: &mut Position, mv: Move) -> String {
+    let generator = MoveGenerator::new();
+    let legal: Vec<Move> = generator
+        .generate_moves(position)
+        .into_values()
+        .flatten()
+        .collect();
+
+    let mut san = match mv.kind() {
+        MoveKind::KingCastle => String::from("O-O"),
+        MoveKind::QueenCastle => String::from("O-O-O"),
+        _ => {
+            let piece = mv.piece();
+            let mut san = String::new();
+            if piece.is_type(PieceType::Pawn) {
+                if mv.is_capture() {
+                    san.push(file(mv.start()));
+                }
+            } else {
+                san.push(piece_letter(piece.r#type));
+                san.push_str(&disambiguation(mv, &legal));
+            }
+            if mv.is_capture() {
+                san.push('x');
+            }
+            san.push_str(&square::name(mv.end()));
+            if let Some(r#type) = mv.promotion() {
+                san.push('=');
+                san.push(piece_letter(r#type));
+            }
+            san
+        }
+    };
+
+    position.make_move(mv);
+    if generator.is_in_check(&position.board, position.current_player) {
+        let has_moves = generator
+            .generate_moves(position)
+            .values()
+            .any(|moves| !moves.is_empty());
+        san.push(if has_moves { '+' } else { '#' });
+    }
+    position.unmake_move();
+    san
+}
+
+// finds the legal move of the position written in standard algebraic
+// notation, check and annotation symbols being optional
+pub fn parse(position: &mut Position, text: &str) -> Result<Move, SanError> {
+    let invalid = || SanError::Invalid(String::from(text));
+    let san = text.trim_end_matches(['+', '#', '!', '?']);
+
+    let legal: Vec<Move> = MoveGenerator::new()
+        .generate_moves(position)
+        .into_values()
+        .flatten()
+        .collect();
+
+    let castle = match san {
+        "O-O" | "0-0" => Some(MoveKind::KingCastle),
+        "O-O-O" | "0-0-0" => Some(MoveKind::QueenCastle),
+        _ => None,
+    };
+    if let Some(kind) = castle {
+        return legal
+            .into_iter()
+            .find(|mv| mv.kind() == kind)
+            .ok_or_else(|| SanError::Illegal(String::from(text)));
+    }
+
+    let mut chars: Vec<char> = san.chars().collect();
+    let r#type = match chars.first().and_then(|c| piece_type(*c)) {
+        Some(r#type) => {
+            chars.remove(0);
+            r#type
+        }
+        None => PieceType::Pawn,
+    };
+
+    // "e8=Q", and "e8Q" which is also seen
+    let mut promotion = None;
+    if let Some(r#type) = chars.last().and_then(|c| piece_type(*c)) {
+        promotion = Some(r#type);
+        chars.pop();
+        if chars.last() == Some(&'=') {
+            chars.pop();
+        }
+    }
+
+    if chars.len() < 2 {
+        return Err(invalid());
+    }
+    let end_name: String = chars.split_off(chars.len() - 2).into_iter().collect();
+    let end = square::parse(&end_name).ok_or_else(invalid)?;
+    if chars.last() == Some(&'x') {
+        chars.pop();
+    }
+
+    // what is left tells the file and/or rank of the moving piece
+    let (mut from_file, mut from_rank) = (None, None);
+    for c in chars {
+        match c {
+            'a'..='h' if from_file.is_none() && from_rank.is_none() => from_file = Some(c),
+            '1'..='8' if from_rank.is_none() => from_rank = Some(c),
+            _ => return Err(invalid()),
+        }
+    }
+
+    let candidates: Vec<Move> = legal
+        .into_iter()
+        .filter(|mv| {
+            mv.end() == end
+                && mv.piece().is_type(r#type)
+                && mv.promotion() == promotion
+                && !mv.is_castle()
+                && from_file.is_none_or(|f| file(mv.start()) == f)
+                && from_rank.is_none_or(|r| rank(mv.start()) == r)
+        })
+        .collect();
+    match candidates.len() {
+        0 => Err(SanError::Illegal(String::from(text))),
+        1 => Ok(candidates[0]),
+        _ => Err(SanError::Ambiguous(String::from(text))),
+    }
+}
+
+// -------------------------------------------
+// ------------ PRIVATE FUNCTIONS ------------
+// -------------------------------------------
+
+// file and/or rank needed to tell the move apart from the moves of the
+// other pieces of the same type going to the same square
+fn disambiguation(mv: Move, legal: &[Move]) -> String {
+    let others: Vec<&Move> = legal
+        .iter()
+        .filter(|other| {
+            other.end() == mv.end()
+                && other.start() != mv.start()
+                && other.piece().is_type(mv.piece().r#type)
+        })
+        .collect();
+    if others.is_empty() {
+        String::new()
+    } else if others
+        .iter()
+        .all(|other| file(other.start()) != file(mv.start()))
+    {
+        file(mv.start()).to_string()
+    } else if others
+        .iter()
+        .all(|other| rank(other.start()) != rank(mv.start()))
+    {
+        rank(mv.start()).to_string()
+    } else {
+        square::name(mv.start())
+    }
+}
+
+fn piece_letter(r#type: PieceType) -> char {
+    match r#type {
+        PieceType::Pawn => 'P',
+        PieceType::Knight => 'N',
+        PieceType::Bishop => 'B',
+        PieceType::Rook => 'R',
+        PieceType::Queen => 'Q',
+        PieceType::King => 'K',
+    }
+}
+
+fn piece_type(letter: char) -> Option<PieceType> {
+    match letter {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position_from(notation: &str) -> Position {
+        Position::from_fen(notation).unwrap()
+    }
+
+    // every legal move of the position written then read back
+    fn round_trip(position: &mut Position) {
+        let legal: Vec<Move> = MoveGenerator::new()
+            .generate_moves(position)
+            .into_values()
+            .flatten()
+            .collect();
+        for mv in legal {
+            let san = write(position, mv);
+            assert_eq!(parse(position, &san), Ok(mv), "{}", san);
+        }
+    }
+
+    fn san_of(position: &mut Position, start: &str, end: &str) -> String {
+        let moves = MoveGenerator::new().generate_moves(position);
+        let (start, end) = (square::parse(start).unwrap(), square::parse(end).unwrap());
+        let mv = Move::find(start, end, None, &moves)
+            .or_else(|| Move::find(start, end, Some(PieceType::Queen), &moves))
+            .unwrap();
+        write(position, mv)
+    }
+
+    #[test]
+    fn writes_every_kind_of_move() {
+        let mut position = position_from(START_WITH_KNIGHTS);
+        assert_eq!(san_of(&mut position, "b1", "d2"), "Nbd2");
+        assert_eq!(san_of(&mut position, "f3", "d2"), "Nfd2");
+        assert_eq!(san_of(&mut position, "e4", "d5"), "exd5");
+
+        // knights on the same file are told apart by their rank
+        let mut position = position_from("4k3/8/8/1N6/8/1N6/8/4K3 w - - 0 1");
+        assert_eq!(san_of(&mut position, "b5", "d4"), "N5d4");
+
+        // three queens may need both file and rank
+        let mut position = position_from(THREE_QUEENS);
+        assert_eq!(san_of(&mut position, "a4", "d1"), "Qa4d1");
+        assert_eq!(san_of(&mut position, "d4", "d1"), "Qdd1");
+        assert_eq!(san_of(&mut position, "a1", "d1"), "Q1d1");
+
+        let mut position = position_from("3r2k1/4P3/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(san_of(&mut position, "e7", "d8"), "exd8=Q+");
+
+        let mut position = position_from("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert_eq!(san_of(&mut position, "e1", "g1"), "O-O");
+        assert_eq!(san_of(&mut position, "e1", "c1"), "O-O-O");
+
+        let mut position = position_from("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(san_of(&mut position, "a1", "a8"), "Ra8#");
+    }
+
+    const START_WITH_KNIGHTS: &str =
+        "rnbqkb1r/ppp1pppp/5n2/3p4/4P3/3P1N2/PPP2PPP/RNBQKB1R w KQkq - 0 3";
+
+    const THREE_QUEENS: &str = "8/7k/8/8/Q2Q4/8/8/Q3K3 w - - 0 1";
+
+    #[test]
+    fn reads_what_it_writes() {
+        for notation in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            THREE_QUEENS,
+            START_WITH_KNIGHTS,
+        ] {
+            round_trip(&mut position_from(notation));
+        }
+    }
+
+    #[test]
+    fn parses_loose_notation() {
+        let mut position = position_from(START_WITH_KNIGHTS);
+        let nbd2 = parse(&mut position, "Nbd2").unwrap();
+        assert_eq!(nbd2.start(), square::parse("b1").unwrap());
+        assert_eq!(parse(&mut position, "Nb1d2"), Ok(nbd2));
+        assert_eq!(parse(&mut position, "exd5!?"), parse(&mut position, "exd5"));
+
+        let mut position = position_from("3r1k2/4P3/8/8/8/8/8/4K3 w - - 0 1");
+        let promotion = parse(&mut position, "exd8=N").unwrap();
+        assert_eq!(promotion.promotion(), Some(PieceType::Knight));
+        assert_eq!(parse(&mut position, "exd8N"), Ok(promotion));
+    }
+
+    #[test]
+    fn rejects_wrong_moves() {
+        let mut position = position_from(START_WITH_KNIGHTS);
+        assert_eq!(
+            parse(&mut position, "Nd2"),
+            Err(SanError::Ambiguous(String::from("Nd2")))
+        );
+        assert_eq!(
+            parse(&mut position, "Ke3"),
+            Err(SanError::Illegal(String::from("Ke3")))
+        );
+        assert_eq!(
+            parse(&mut position, "O-O"),
+            Err(SanError::Illegal(String::from("O-O")))
+        );
+        for text in ["", "N", "Nz9", "e9", "Nbbd2"] {
+            assert_eq!(
+                parse(&mut position, text),
+                Err(SanError::Invalid(String::from(text)))
+            );
+        }
+    }
+}
@@ -0,0 +1,44 @@
+// Squares are numbered row by row from a8 = 0 to h1 = 63, these functions
+// convert them from and to their names such as "e4"
+
+pub fn parse(name: &str) -> Option<usize> {
+    let bytes = name.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1])
+    {
+        return None;
+    }
+    Some((7 - (bytes[1] - b'1') as usize) * 8 + (bytes[0] - b'a') as usize)
+}
+
+pub fn name(square: usize) -> String {
+    format!("{}{}", file(square), rank(square))
+}
+
+// letter of the column, from 'a' to 'h'
+pub fn file(square: usize) -> char {
+    (b'a' + (square % 8) as u8) as char
+}
+
+// digit of the row, from '8' for the first one down to '1'
+pub fn rank(square: usize) -> char {
+    (b'8' - (square / 8) as u8) as char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_match_indices() {
+        for (index, expected) in [(0, "a8"), (7, "h8"), (28, "e5"), (36, "e4"), (63, "h1")] {
+            assert_eq!(name(index), expected);
+            assert_eq!(parse(expected), Some(index));
+        }
+        for square in 0..64 {
+            assert_eq!(parse(&name(square)), Some(square));
+        }
+        for wrong in ["", "e", "e44", "i1", "a0", "a9", "E4"] {
+            assert_eq!(parse(wrong), None);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use super::piece::PColor;
+
+use std::fmt;
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum DrawReason {
+    // claimed by a player
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    // declared automatically
+    FivefoldRepetition,
+    SeventyFiveMoveRule,
+    InsufficientMaterial,
+}
+
+impl fmt::Display for DrawReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DrawReason::ThreefoldRepetition => write!(f, "threefold repetition"),
+            DrawReason::FiftyMoveRule => write!(f, "the fifty-move rule"),
+            DrawReason::FivefoldRepetition => write!(f, "fivefold repetition"),
+            DrawReason::SeventyFiveMoveRule => write!(f, "the seventy-five-move rule"),
+            DrawReason::InsufficientMaterial => write!(f, "insufficient material"),
+        }
+    }
+}
+
+// Decisive statuses carry the color of the winning player
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum GameStatus {
+    Ongoing,
+    Checkmate(PColor),
+    Stalemate,
+    Draw(DrawReason),
+    Resignation(PColor),
+    Timeout(PColor),
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        *self != GameStatus::Ongoing
+    }
+
+    // score as written in PGN, "*" while the game goes on
+    pub fn result(&self) -> &'static str {
+        match self {
+            GameStatus::Ongoing => "*",
+            GameStatus::Stalemate | GameStatus::Draw(_) => "1/2-1/2",
+            GameStatus::Checkmate(color)
+            | GameStatus::Resignation(color)
+            | GameStatus::Timeout(color) => match color {
+                PColor::White => "1-0",
+                PColor::Black => "0-1",
+            },
+        }
+    }
+}
+
+impl fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameStatus::Ongoing => write!(f, "Game in progress"),
+            GameStatus::Checkmate(color) => write!(f, "{} wins by checkmate", color),
+            GameStatus::Stalemate => write!(f, "Draw by stalemate"),
+            GameStatus::Draw(reason) => write!(f, "Draw by {}", reason),
+            GameStatus::Resignation(color) => write!(f, "{} wins by resignation", color),
+            GameStatus::Timeout(color) => write!(f, "{} wins on time", color),
+        }
+    }
+}
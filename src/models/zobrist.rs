@@ -0,0 +1,176 @@
+use super::bitboard;
+use super::piece::{PColor, Piece, PieceType};
+use super::position::{CastlingRights, Position};
+
+use std::sync::OnceLock;
+
+// Random keys xored together to identify a position: one per piece on each
+// square, one per castling right, one per en passant file and one for black
+// to move. Making a move only has to xor the keys of what changed.
+pub struct Zobrist {
+    // indexed by color, piece type then square
+    pieces: [[[u64; 64]; 6]; 2],
+    // white king side, white queen side, black king side, black queen side
+    castling: [u64; 4],
+    en_passant: [u64; 8],
+    black_to_move: u64,
+}
+
+static ZOBRIST: OnceLock<Zobrist> = OnceLock::new();
+
+impl Zobrist {
+    pub fn get() -> &'static Zobrist {
+        ZOBRIST.get_or_init(Zobrist::new)
+    }
+
+    pub fn piece(&self, piece: Piece, square: usize) -> u64 {
+        self.pieces[piece.color as usize][piece.r#type as usize][square]
+    }
+
+    pub fn castling(&self, rights: CastlingRights) -> u64 {
+        [
+            rights.white_king_side,
+            rights.white_queen_side,
+            rights.black_king_side,
+            rights.black_queen_side,
+        ]
+        .iter()
+        .zip(self.castling)
+        .filter(|(allowed, _)| **allowed)
+        .fold(0, |hash, (_, key)| hash ^ key)
+    }
+
+    // the en passant square only counts when a pawn of the player to move
+    // stands ready to take, otherwise the position is the same as without it
+    pub fn en_passant(&self, position: &Position) -> u64 {
+        let target = match position.en_passant {
+            None => return 0,
+            Some(target) => target,
+        };
+        let player = position.current_player;
+        let takers = bitboard::Attacks::get().pawn(player.opposite(), target)
+            & position.board.pieces(PieceType::Pawn, player);
+        if takers == 0 {
+            return 0;
+        }
+        self.en_passant[target % 8]
+    }
+
+    pub fn side(&self, color: PColor) -> u64 {
+        match color {
+            PColor::White => 0,
+            PColor::Black => self.black_to_move,
+        }
+    }
+
+    // key of the position computed from scratch
+    pub fn hash(&self, position: &Position) -> u64 {
+        let mut hash: u64 = 0;
+        for (square, piece) in position.board.squares().iter().enumerate() {
+            if let Some(p) = piece {
+                hash ^= self.piece(*p, square);
+            }
+        }
+        hash ^ self.castling(position.castling)
+            ^ self.en_passant(position)
+            ^ self.side(position.current_player)
+    }
+
+    // -------------------------------------------
+    // ------------ PRIVATE FUNCTIONS ------------
+    // -------------------------------------------
+
+    fn new() -> Zobrist {
+        // the seed is fixed so a position gets the same key on every run
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = move || {
+            // splitmix64
+            seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+
+        let mut zobrist = Zobrist {
+            pieces: [[[0; 64]; 6]; 2],
+            castling: [0; 4],
+            en_passant: [0; 8],
+            black_to_move: 0,
+        };
+        for color in zobrist.pieces.iter_mut() {
+            for r#type in color.iter_mut() {
+                r#type.iter_mut().for_each(|key| *key = random());
+            }
+        }
+        zobrist.castling.iter_mut().for_each(|key| *key = random());
+        zobrist
+            .en_passant
+            .iter_mut()
+            .for_each(|key| *key = random());
+        zobrist.black_to_move = random();
+        zobrist
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::r#move::{Move, MoveGenerator};
+    use crate::models::square;
+
+    fn play(position: &mut Position, moves: &[&str]) {
+        let generator = MoveGenerator::new();
+        for name in moves {
+            let (start, end) = (
+                square::parse(&name[..2]).unwrap(),
+                square::parse(&name[2..]).unwrap(),
+            );
+            let possible = generator.generate_moves(position);
+            let mv = Move::find(start, end, None, &possible).unwrap();
+            position.make_move(mv);
+        }
+    }
+
+    #[test]
+    fn transpositions_share_a_key() {
+        let mut first = Position::new();
+        play(&mut first, &["e2e4", "e7e5", "g1f3"]);
+        let mut second = Position::new();
+        play(&mut second, &["g1f3", "e7e5", "e2e4"]);
+        assert_eq!(first.hash, second.hash);
+
+        // the knights going back and forth lead to the starting position
+        let mut position = Position::new();
+        play(&mut position, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(position.hash, Position::new().hash);
+    }
+
+    #[test]
+    fn states_are_part_of_the_key() {
+        let key = |notation: &str| Position::from_fen(notation).unwrap().hash;
+
+        assert_ne!(
+            key("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1"),
+            key("4k3/8/8/8/8/8/8/R3K2R w K - 0 1")
+        );
+        assert_ne!(
+            key("4k3/8/8/8/8/8/8/R3K2R w - - 0 1"),
+            key("4k3/8/8/8/8/8/8/R3K2R b - - 0 1")
+        );
+        // en passant only counts when a pawn can take
+        assert_ne!(
+            key("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1"),
+            key("4k3/8/8/3pP3/8/8/8/4K3 w - - 0 1")
+        );
+        assert_eq!(
+            key("4k3/8/8/3p4/8/8/4P3/4K3 w - d6 0 1"),
+            key("4k3/8/8/3p4/8/8/4P3/4K3 w - - 0 1")
+        );
+        // the clocks do not change the position
+        assert_eq!(
+            key("4k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+            key("4k3/8/8/8/8/8/8/4K3 w - - 12 40")
+        );
+    }
+}